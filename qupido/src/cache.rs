@@ -0,0 +1,626 @@
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::{container::{Container, ContainerData}, node::Node, parameters::WholeNumber, QupidoResult, QupidoError};
+
+/// Feeds a value into the hash the [`NodeCache`] uses to tell whether the
+/// inputs of a node changed since its outputs were cached.
+///
+/// Cache keys are kept across runs, so implementations have to write the
+/// same bytes on every platform and compiler: integers as little-endian
+/// bytes and variable length data prefixed with its length, rather than
+/// through [`Hash`](std::hash::Hash), whose output may change.
+pub trait Fingerprint {
+    fn fingerprint(&self, hasher: &mut dyn Hasher);
+}
+
+macro_rules! fingerprint_le_bytes {
+    ($($t:ty),*) => {
+        $(impl Fingerprint for $t {
+            fn fingerprint(&self, hasher: &mut dyn Hasher) {
+                hasher.write(&self.to_le_bytes())
+            }
+        })*
+    };
+}
+
+fingerprint_le_bytes!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128);
+
+impl Fingerprint for isize {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        (*self as i64).fingerprint(hasher)
+    }
+}
+
+impl Fingerprint for usize {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        (*self as u64).fingerprint(hasher)
+    }
+}
+
+impl Fingerprint for bool {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        (*self as u8).fingerprint(hasher)
+    }
+}
+
+impl Fingerprint for char {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        (*self as u32).fingerprint(hasher)
+    }
+}
+
+impl Fingerprint for String {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.len().fingerprint(hasher);
+        hasher.write(self.as_bytes())
+    }
+}
+
+impl Fingerprint for f32 {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.to_bits().fingerprint(hasher)
+    }
+}
+
+impl Fingerprint for f64 {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.to_bits().fingerprint(hasher)
+    }
+}
+
+impl<T> Fingerprint for Option<T> where T: Fingerprint {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        match self {
+            Some(v) => {
+                1_u8.fingerprint(hasher);
+                v.fingerprint(hasher);
+            },
+            None => 0_u8.fingerprint(hasher)
+        }
+    }
+}
+
+impl<T> Fingerprint for Vec<T> where T: Fingerprint {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.len().fingerprint(hasher);
+        self.iter().for_each(|v| v.fingerprint(hasher));
+    }
+}
+
+impl<K, V> Fingerprint for BTreeMap<K, V> where K: Fingerprint, V: Fingerprint {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.len().fingerprint(hasher);
+        for (k, v) in self {
+            k.fingerprint(hasher);
+            v.fingerprint(hasher);
+        }
+    }
+}
+
+impl Fingerprint for WholeNumber {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        self.int.fingerprint(hasher)
+    }
+}
+
+/// Every value is tagged with its kind, and objects are hashed in key order,
+/// whatever order they were built or parsed in.
+impl Fingerprint for Value {
+    fn fingerprint(&self, hasher: &mut dyn Hasher) {
+        match self {
+            Value::Null => 0_u8.fingerprint(hasher),
+            Value::Bool(b) => {
+                1_u8.fingerprint(hasher);
+                b.fingerprint(hasher);
+            },
+            Value::Number(n) => {
+                2_u8.fingerprint(hasher);
+                n.to_string().fingerprint(hasher);
+            },
+            Value::String(s) => {
+                3_u8.fingerprint(hasher);
+                s.fingerprint(hasher);
+            },
+            Value::Array(values) => {
+                4_u8.fingerprint(hasher);
+                values.len().fingerprint(hasher);
+                values.iter().for_each(|v| v.fingerprint(hasher));
+            },
+            Value::Object(entries) => {
+                5_u8.fingerprint(hasher);
+                let mut entries: Vec<_> = entries.iter().collect();
+                entries.sort_by_key(|(k, _)| *k);
+                entries.len().fingerprint(hasher);
+                for (k, v) in entries {
+                    k.fingerprint(hasher);
+                    v.fingerprint(hasher);
+                }
+            }
+        }
+    }
+}
+
+/// 64 bit FNV-1a over the bytes written, the hash cache keys are made with.
+/// Codecs registered with [`NodeCache::register_with`] can use it to boil a
+/// large value down to a few bytes. Only [`Hasher::write`] is stable, the
+/// other methods write native-endian bytes.
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    pub fn new() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Collects the bytes a [`Fingerprint`] writes.
+struct Written(Vec<u8>);
+
+impl Hasher for Written {
+    fn finish(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write(&self.0);
+        hasher.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes)
+    }
+}
+
+/// What a cache codec computes, possibly running a query to get there.
+pub type CacheFuture<T> = BoxFuture<'static, QupidoResult<T>>;
+
+type FingerprintFn = dyn Fn(&dyn ContainerData) -> CacheFuture<Vec<u8>> + Send + Sync;
+type EncodeFn = dyn Fn(&dyn ContainerData) -> CacheFuture<Value> + Send + Sync;
+type DecodeFn = dyn Fn(Value) -> QupidoResult<Arc<dyn ContainerData>> + Send + Sync;
+
+struct CacheType {
+    name: &'static str,
+    fingerprint: Box<FingerprintFn>,
+    encode: Box<EncodeFn>,
+    decode: Box<DecodeFn>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    node: String,
+    version: String,
+    outputs: BTreeMap<String, CachedValue>
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedValue {
+    #[serde(rename = "type")]
+    type_name: String,
+    value: Value
+}
+
+/// Keeps node outputs on disk so unchanged nodes don't have to run again.
+///
+/// Only nodes with a [`Node::version`] are cached. A node is skipped, and its
+/// outputs restored, when its version and the fingerprints of all its inputs
+/// match a previous run; bump the version whenever the node's code changes.
+/// Inputs and outputs have to be of a type registered with
+/// [`NodeCache::register`] or [`NodeCache::register_with`], nodes using
+/// other types always run. Parameter types are registered from the start.
+///
+/// [`Runner::run_async`](crate::runner::Runner::run_async) waits for codecs
+/// that have to run a query, like the DataFrame one of `qupido_data`. The
+/// sync runners only cache values whose codecs are done at once.
+///
+/// Entries are JSON files in one directory per node, see
+/// [`NodeCache::evict`] and [`NodeCache::clear`] to remove them.
+#[derive(Clone)]
+pub struct NodeCache {
+    dir: PathBuf,
+    force: bool,
+    types: HashMap<TypeId, Arc<CacheType>>
+}
+
+impl NodeCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let mut cache = NodeCache {
+            dir: dir.into(),
+            force: false,
+            types: HashMap::new()
+        };
+        cache.register::<bool>();
+        cache.register::<i64>();
+        cache.register::<f64>();
+        cache.register::<String>();
+        cache.register::<Value>();
+        cache.register::<WholeNumber>();
+        cache
+    }
+
+    /// Lets values of type `T` be fingerprinted as node inputs and stored as
+    /// node outputs.
+    pub fn register<T>(&mut self)
+        where T: ContainerData + Fingerprint + Serialize + DeserializeOwned
+    {
+        self.register_with::<T>(
+            |v| {
+                let mut written = Written(vec![]);
+                v.fingerprint(&mut written);
+                future::ready(Ok(written.0)).boxed()
+            },
+            |v| future::ready(serde_json::to_value(v).map_err(Into::into)).boxed(),
+            |v| Ok(serde_json::from_value(v)?)
+        );
+    }
+
+    /// Like [`NodeCache::register`], for types that can't implement the
+    /// traits or need a query to be fingerprinted or encoded. `fingerprint`
+    /// returns the bytes, written to the key as they are, telling values
+    /// apart, and `decode` reads back what `encode` stored.
+    pub fn register_with<T>(
+        &mut self,
+        fingerprint: impl Fn(&T) -> CacheFuture<Vec<u8>> + Send + Sync + 'static,
+        encode: impl Fn(&T) -> CacheFuture<Value> + Send + Sync + 'static,
+        decode: impl Fn(Value) -> QupidoResult<T> + Send + Sync + 'static
+    )
+        where T: ContainerData
+    {
+        let cache_type = CacheType {
+            name: std::any::type_name::<T>(),
+            fingerprint: Box::new(move |v| fingerprint(v.downcast_ref::<T>().expect("registered by type id"))),
+            encode: Box::new(move |v| encode(v.downcast_ref::<T>().expect("registered by type id"))),
+            decode: Box::new(move |v| Ok(Arc::new(decode(v)?)))
+        };
+        self.types.insert(TypeId::of::<T>(), Arc::new(cache_type));
+    }
+
+    pub fn with_type<T>(mut self) -> Self
+        where T: ContainerData + Fingerprint + Serialize + DeserializeOwned
+    {
+        self.register::<T>();
+        self
+    }
+
+    /// Runs every node even if its outputs are cached, replacing the cached
+    /// outputs with the new ones.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The same codecs, storing entries under `dir`.
+    pub(crate) fn in_dir(mut self, dir: PathBuf) -> Self {
+        self.dir = dir;
+        self.force = false;
+        self
+    }
+
+    /// Removes the cached outputs of the node named `node`, returning how
+    /// many entries there were.
+    pub fn evict(&self, node: &str) -> QupidoResult<usize> {
+        let dir = self.node_dir(node);
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let entries = std::fs::read_dir(&dir)?.count();
+        std::fs::remove_dir_all(&dir)?;
+        Ok(entries)
+    }
+
+    /// Removes all cached outputs.
+    pub fn clear(&self) -> QupidoResult {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    /// The key the outputs of `node` are cached under for these inputs, or
+    /// `None` if the node can't be cached.
+    pub(crate) async fn key(&self, node: &Node, inputs: &Container) -> Option<String> {
+        let version = node.version.as_ref()?;
+        let mut hasher = StableHasher::new();
+        version.fingerprint(&mut hasher);
+        for (local, _) in node.inputs.pairs() {
+            let id = local.get_id();
+            let Some(value) = inputs.data.get(&id) else {
+                debug!(input = %id, "input missing, not caching");
+                return None;
+            };
+            let Some(cache_type) = self.type_of(value.as_ref()) else {
+                debug!(input = %id, "input type not registered, not caching");
+                return None;
+            };
+            id.fingerprint(&mut hasher);
+            cache_type.name.to_string().fingerprint(&mut hasher);
+            match (cache_type.fingerprint)(value.as_ref()).await {
+                Ok(bytes) => hasher.write(&bytes),
+                Err(e) => {
+                    warn!(input = %id, error = %e, "could not fingerprint input, not caching");
+                    return None;
+                }
+            }
+        }
+        for (local, _) in node.outputs.pairs() {
+            local.get_id().fingerprint(&mut hasher);
+        }
+        Some(format!("{:016x}", hasher.finish()))
+    }
+
+    /// Cached outputs of `node` for `key`. Entries that can't be read are
+    /// treated as missing.
+    pub(crate) fn restore(&self, node: &Node, key: &str) -> Option<Container> {
+        if self.force {
+            return None;
+        }
+        let outputs = self.entry(node, key)?;
+        info!(key, "restored outputs from cache");
+        Some(outputs)
+    }
+
+    /// The outputs of `node` stored under `key`, ignoring the entry if it
+    /// can't be read.
+    pub(crate) fn entry(&self, node: &Node, key: &str) -> Option<Container> {
+        let path = self.node_dir(&node.name).join(format!("{}.json", key));
+        if !path.exists() {
+            return None;
+        }
+        match self.read(&path) {
+            Ok(outputs) => Some(outputs),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "ignoring unreadable cache entry");
+                None
+            }
+        }
+    }
+
+    /// Caches the outputs of `node` under `key`. Failing to do so is logged,
+    /// the run goes on without the entry.
+    pub(crate) async fn store(&self, node: &Node, key: &str, outputs: &Container) {
+        let mut values = BTreeMap::new();
+        for (id, value) in &outputs.data {
+            let Some(cache_type) = self.type_of(value.as_ref()) else {
+                debug!(output = %id, "output type not registered, not caching");
+                return;
+            };
+            match (cache_type.encode)(value.as_ref()).await {
+                Ok(v) => values.insert(id.clone(), CachedValue { type_name: cache_type.name.to_string(), value: v }),
+                Err(e) => {
+                    warn!(output = %id, error = %e, "could not encode output for the cache");
+                    return;
+                }
+            };
+        }
+
+        let entry = CacheEntry {
+            node: node.name.clone(),
+            version: node.version.clone().unwrap_or_default(),
+            outputs: values
+        };
+        let dir = self.node_dir(&node.name);
+        let written = serde_json::to_vec(&entry).map_err(Into::into).and_then(|json| {
+            std::fs::create_dir_all(&dir)?;
+            // write next to the entry and rename, so readers never see half an entry
+            let tmp = dir.join(format!("{}.json.tmp", key));
+            std::fs::write(&tmp, json)?;
+            std::fs::rename(&tmp, dir.join(format!("{}.json", key)))?;
+            QupidoResult::Ok(())
+        });
+        if let Err(e) = written {
+            warn!(dir = %dir.display(), error = %e, "could not write cache entry");
+        }
+    }
+
+    fn read(&self, path: &Path) -> QupidoResult<Container> {
+        let entry: CacheEntry = serde_json::from_slice(&std::fs::read(path)?)?;
+        let mut outputs = Container::new();
+        for (id, cached) in entry.outputs {
+            let cache_type = self.types.values()
+                .find(|t| t.name == cached.type_name)
+                .ok_or_else(|| QupidoError::DatasetError(format!("cached output '{}' has unregistered type {}", id, cached.type_name)))?;
+            outputs.data.insert(id, (cache_type.decode)(cached.value)?);
+        }
+        Ok(outputs)
+    }
+
+    fn type_of(&self, value: &dyn ContainerData) -> Option<&CacheType> {
+        self.types.get(&value.as_any().type_id()).map(|t| t.as_ref())
+    }
+
+    /// Node names are free text, so directories are named after a cleaned up
+    /// name plus a hash of the real one to keep them apart.
+    fn node_dir(&self, node: &str) -> PathBuf {
+        let clean: String = node.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        let mut hasher = StableHasher::new();
+        hasher.write(node.as_bytes());
+        self.dir.join(format!("{}-{:08x}", clean, hasher.finish() as u32))
+    }
+}
+
+impl Debug for NodeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut types: Vec<_> = self.types.values().map(|t| t.name).collect();
+        types.sort();
+        f.debug_struct("NodeCache").field("dir", &self.dir).field("force", &self.force).field("types", &types).finish()
+    }
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_node_cache() -> QupidoResult {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::{id, param, parameters::Parameters, pipeline::Pipeline, runner::Runner};
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let scale = |version: &str, calls: Arc<AtomicUsize>| Node::new([id("xs"), param("factor")], [id("scaled")], move |ctx| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        let xs: &Vec<i64> = ctx.inputs.get("xs")?;
+        let factor: &i64 = ctx.inputs.get("params:factor")?;
+        let mut r = Container::new();
+        r.insert("scaled", xs.iter().map(|x| x * factor).collect::<Vec<_>>())?;
+        Ok(r)
+    }).name("scale").version(version);
+    let pipeline = Pipeline::from_nodes(&[scale("1", calls.clone())])?;
+
+    let dir = std::env::temp_dir().join(format!("qupido_cache_{}", std::process::id()));
+    let cache = NodeCache::new(&dir).with_type::<Vec<i64>>();
+    let runner = |factor: i64, cache: NodeCache| Runner::sequential()
+        .with_parameters(Arc::new(Parameters::from_value(serde_json::json!({ "factor": factor })).unwrap()))
+        .with_cache(Arc::new(cache));
+    let mut container = Container::new();
+    container.insert("xs", vec![1_i64, 2, 3])?;
+
+    let result = runner(2, cache.clone()).run(&pipeline, &container)?;
+    assert_eq!(*result.get::<Vec<i64>>("scaled")?, vec![2, 4, 6]);
+    let result = runner(2, cache.clone()).run(&pipeline, &container)?;
+    assert_eq!(*result.get::<Vec<i64>>("scaled")?, vec![2, 4, 6]);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // a changed parameter, a forced run and a new version all run the node again
+    runner(3, cache.clone()).run(&pipeline, &container)?;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    runner(2, cache.clone().force(true)).run(&pipeline, &container)?;
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    let bumped = Pipeline::from_nodes(&[scale("2", calls.clone())])?;
+    futures::executor::block_on(runner(2, cache.clone()).run_async(&bumped, &container))?;
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    futures::executor::block_on(runner(2, cache.clone()).run_async(&bumped, &container))?;
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    assert_eq!(cache.evict("scale")?, 3);
+    runner(2, cache.clone()).run(&pipeline, &container)?;
+    assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+    // without a registered input type the node always runs
+    runner(2, NodeCache::new(&dir)).run(&pipeline, &container)?;
+    runner(2, NodeCache::new(&dir)).run(&pipeline, &container)?;
+    assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+    cache.clear()?;
+    assert!(!dir.exists());
+    Ok(())
+}
+
+#[test]
+fn test_stable_fingerprints() -> QupidoResult {
+    // the bytes hashed are fixed, so keys of earlier runs stay valid
+    let mut hasher = StableHasher::new();
+    vec!["ab".to_string()].fingerprint(&mut hasher);
+    (-2_isize).fingerprint(&mut hasher);
+    0.5_f64.fingerprint(&mut hasher);
+    assert_eq!(hasher.finish(), 0xe183fec5b19c9b11);
+
+    // strings are length prefixed, so their boundaries count
+    let hash = |parts: &[&str]| {
+        let mut hasher = StableHasher::new();
+        parts.iter().for_each(|p| p.to_string().fingerprint(&mut hasher));
+        hasher.finish()
+    };
+    assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+
+    // objects hash the same whatever order their keys are in
+    let value = |json: &str| -> QupidoResult<u64> {
+        let mut hasher = StableHasher::new();
+        serde_json::from_str::<Value>(json)?.fingerprint(&mut hasher);
+        Ok(hasher.finish())
+    };
+    assert_eq!(value(r#"{"b": [1, "x"], "a": {"d": null, "c": true}}"#)?, value(r#"{"a": {"c": true, "d": null}, "b": [1, "x"]}"#)?);
+    assert_ne!(value(r#"["1"]"#)?, value("[1]")?);
+    Ok(())
+}
+
+#[test]
+fn test_register_with() -> QupidoResult {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Poll;
+    use crate::{id, pipeline::Pipeline, runner::Runner};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Celsius(f64);
+
+    // a codec that isn't done at once, like one running a query
+    fn later<T: Send + 'static>(value: T) -> CacheFuture<T> {
+        let mut value = Some(value);
+        let mut waited = false;
+        future::poll_fn(move |cx| {
+            if waited {
+                return Poll::Ready(Ok(value.take().expect("polled once more")));
+            }
+            waited = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }).boxed()
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let warm = Node::new(id("temp"), id("warmer"), move |ctx| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let mut r = Container::new();
+        r.insert("warmer", Celsius(ctx.inputs.get::<Celsius>("temp")?.0 + 1.0))?;
+        Ok(r)
+    }).name("warm").version("1");
+    let pipeline = Pipeline::from_nodes(&[warm])?;
+
+    let dir = std::env::temp_dir().join(format!("qupido_cache_with_{}", std::process::id()));
+    let mut cache = NodeCache::new(&dir);
+    cache.register_with::<Celsius>(
+        |c| later(c.0.to_bits().to_le_bytes().to_vec()),
+        |c| later(serde_json::json!(c.0)),
+        |v| Ok(Celsius(serde_json::from_value(v)?))
+    );
+    let runner = Runner::sequential().with_cache(Arc::new(cache.clone()));
+    let mut container = Container::new();
+    container.insert("temp", Celsius(20.0))?;
+
+    // the sync runner can't wait for the codec, so the node always runs
+    runner.run(&pipeline, &container)?;
+    runner.run(&pipeline, &container)?;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    futures::executor::block_on(runner.run_async(&pipeline, &container))?;
+    let result = futures::executor::block_on(runner.run_async(&pipeline, &container))?;
+    assert_eq!(*result.get::<Celsius>("warmer")?, Celsius(21.0));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    cache.clear()?;
+    Ok(())
+}
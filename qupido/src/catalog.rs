@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+
+use crate::{container::{Container, ContainerData}, Source, QupidoResult, QupidoError};
+
+pub type DatasetFuture<'a, T = ()> = BoxFuture<'a, QupidoResult<T>>;
+
+/// A place a single source can be loaded from and saved to.
+///
+/// The methods return boxed futures so file and table backed datasets can do
+/// their IO asynchronously; the sync runner blocks on them, unless the
+/// dataset [needs a runtime](Dataset::needs_runtime).
+pub trait Dataset: Debug + Send + Sync {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>>;
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_>;
+    fn exists(&self) -> DatasetFuture<'_, bool>;
+
+    /// Whether saved data outlives the run. The runner only saves node
+    /// outputs to persistent datasets.
+    fn is_persistent(&self) -> bool {
+        true
+    }
+
+    /// Whether the futures need an async runtime to make progress, like
+    /// DataFusion's need tokio. The sync runners can't provide one and
+    /// refuse to run with such datasets.
+    fn needs_runtime(&self) -> bool {
+        false
+    }
+}
+
+impl<D> Dataset for Arc<D> where D: Dataset + ?Sized {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        self.as_ref().load()
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        self.as_ref().save(data)
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        self.as_ref().exists()
+    }
+
+    fn is_persistent(&self) -> bool {
+        self.as_ref().is_persistent()
+    }
+
+    fn needs_runtime(&self) -> bool {
+        self.as_ref().needs_runtime()
+    }
+}
+
+/// Keeps a value in memory, mostly useful to hand inputs to a run.
+#[derive(Debug, Default)]
+pub struct MemoryDataset {
+    data: Mutex<Option<Arc<dyn ContainerData>>>
+}
+
+impl MemoryDataset {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_data(data: impl ContainerData) -> Self {
+        MemoryDataset {
+            data: Mutex::new(Some(Arc::new(data)))
+        }
+    }
+}
+
+impl Dataset for MemoryDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        let data = self.data.lock().expect("memory dataset lock").clone();
+        Box::pin(async move {
+            data.ok_or_else(|| QupidoError::DatasetError("memory dataset is empty".to_string()))
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        *self.data.lock().expect("memory dataset lock") = Some(data);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        let exists = self.data.lock().expect("memory dataset lock").is_some();
+        Box::pin(async move { Ok(exists) })
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// Maps sources to the datasets they are loaded from and saved to.
+///
+/// When a runner is given a catalog, pipeline inputs missing from the input
+/// container are loaded from it, and node outputs with a persistent dataset
+/// registered are saved as soon as the node finishes.
+#[derive(Clone, Debug, Default)]
+pub struct DataCatalog {
+    datasets: HashMap<Source, Arc<dyn Dataset>>
+}
+
+impl DataCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, source: Source, dataset: impl Dataset + 'static) -> QupidoResult {
+        self.add_shared(source, Arc::new(dataset))
+    }
+
+    pub fn add_shared(&mut self, source: Source, dataset: Arc<dyn Dataset>) -> QupidoResult {
+        if self.datasets.contains_key(&source) {
+            return Err(QupidoError::DuplicateData(source.get_id()));
+        }
+
+        self.datasets.insert(source, dataset);
+        Ok(())
+    }
+
+    pub fn with(mut self, source: Source, dataset: impl Dataset + 'static) -> QupidoResult<Self> {
+        self.add(source, dataset)?;
+        Ok(self)
+    }
+
+    pub fn get(&self, source: &Source) -> Option<&Arc<dyn Dataset>> {
+        self.datasets.get(source)
+    }
+
+    pub fn sources(&self) -> Vec<Source> {
+        let mut r: Vec<_> = self.datasets.keys().cloned().collect();
+        r.sort();
+        r
+    }
+
+    pub async fn load(&self, source: &Source) -> QupidoResult<Arc<dyn ContainerData>> {
+        let dataset = self.get(source).ok_or(QupidoError::DataNotFound(source.get_id()))?;
+        dataset.load().await
+    }
+
+    pub async fn save(&self, source: &Source, data: Arc<dyn ContainerData>) -> QupidoResult {
+        let dataset = self.get(source).ok_or(QupidoError::DataNotFound(source.get_id()))?;
+        dataset.save(data).await
+    }
+
+    pub async fn exists(&self, source: &Source) -> QupidoResult<bool> {
+        match self.get(source) {
+            Some(dataset) => dataset.exists().await,
+            None => Ok(false)
+        }
+    }
+
+    /// Loads every source that isn't in the container yet and has a dataset
+    /// registered, returning the loaded ones. Sources without a dataset are
+    /// left for the run to report.
+    pub async fn load_missing(&self, sources: &[Source], container: &mut Container) -> QupidoResult<Vec<Source>> {
+        let mut loaded = vec![];
+        for source in sources {
+            if container.contains(&source.get_id()) || self.get(source).is_none() {
+                continue;
+            }
+
+            let data = self.load(source).await?;
+            container.data.insert(source.get_id(), data);
+            loaded.push(source.clone());
+        }
+
+        Ok(loaded)
+    }
+
+    /// Saves the given sources from the container to their persistent
+    /// datasets, returning the saved ones.
+    pub async fn save_persistent(&self, sources: &[Source], container: &Container) -> QupidoResult<Vec<Source>> {
+        let mut saved = vec![];
+        for source in sources {
+            let Some(dataset) = self.get(source) else {
+                continue;
+            };
+            if !dataset.is_persistent() {
+                continue;
+            }
+
+            let data = container.data.get(&source.get_id()).ok_or(QupidoError::DataNotFound(source.get_id()))?;
+            dataset.save(data.clone()).await?;
+            saved.push(source.clone());
+        }
+
+        Ok(saved)
+    }
+}
+
+
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct RecordingDataset {
+    saved: Mutex<Vec<i64>>
+}
+
+#[cfg(test)]
+impl Dataset for RecordingDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        let last = self.saved.lock().unwrap().last().cloned();
+        Box::pin(async move {
+            let last = last.ok_or_else(|| QupidoError::DatasetError("nothing saved".to_string()))?;
+            Ok(Arc::new(last) as Arc<dyn ContainerData>)
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        Box::pin(async move {
+            let v = data.downcast_ref::<i64>().ok_or_else(|| QupidoError::DatasetError("expected i64".to_string()))?;
+            self.saved.lock().unwrap().push(*v);
+            Ok(())
+        })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        Box::pin(async move { Ok(!self.saved.lock().unwrap().is_empty()) })
+    }
+}
+
+#[test]
+fn test_catalog_loads_inputs_and_saves_outputs() -> QupidoResult {
+    use crate::{id, node::Node, pipeline::Pipeline, runner::Runner};
+
+    let n = Node::new([id("x"), id("y")], [id("sum")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let y: &i64 = ctx.inputs.get("y")?;
+        let mut r = Container::new();
+        r.insert("sum", x + y)?;
+        Ok(r)
+    });
+    let pipeline = Pipeline::from_nodes(&[n])?;
+
+    let recorded = Arc::new(RecordingDataset::default());
+    let catalog = DataCatalog::new()
+        .with(id("x"), MemoryDataset::with_data(2_i64))?
+        .with(id("y"), MemoryDataset::with_data(5_i64))?
+        .with(id("sum"), recorded.clone())?;
+
+    // inputs already in the container win over the catalog
+    let mut container = Container::new();
+    container.insert("y", 10_i64)?;
+
+    let runner = Runner::sequential().with_catalog(Arc::new(catalog));
+    let result = runner.run(&pipeline, &container)?;
+    assert_eq!(*result.get::<i64>("sum")?, 12);
+    assert_eq!(*recorded.saved.lock().unwrap(), vec![12]);
+
+    let result = futures::executor::block_on(runner.run_async(&pipeline, &Container::new()))?;
+    assert_eq!(*result.get::<i64>("sum")?, 7);
+    assert_eq!(*recorded.saved.lock().unwrap(), vec![12, 7]);
+
+    Ok(())
+}
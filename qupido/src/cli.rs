@@ -0,0 +1,458 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use uuid::Uuid;
+
+use crate::{cache::NodeCache, config::{ConfigLoader, DatasetTypes}, container::Container, html::HtmlReport, id, pipeline::Pipeline, registry::{PipelineRegistry, DEFAULT_PIPELINE}, report::OutputSizes, resume::RunHistory, runner::Runner, Source, Tag, QupidoResult, QupidoError};
+
+/// Where projects keep their run history by default, relative to the
+/// directory `qupido` runs in.
+pub const DEFAULT_HISTORY_DIR: &str = "data/runs";
+
+/// What the `qupido` command needs to know about a project: its pipelines,
+/// the dataset types its catalog config uses and, optionally, where to cache
+/// node outputs. Reports of past runs and the checkpoints failed runs are
+/// resumed from are kept in [`DEFAULT_HISTORY_DIR`], unless
+/// [`Project::with_history`] puts them elsewhere.
+///
+/// A project gets its `qupido` binary by handing itself to [`main`]:
+///
+/// ```ignore
+/// #[tokio::main]
+/// async fn main() -> std::process::ExitCode {
+///     qupido::cli::main(Project::new(register_pipelines()?)).await
+/// }
+/// ```
+pub struct Project {
+    pub registry: PipelineRegistry,
+    pub dataset_types: DatasetTypes,
+    pub cache: Option<NodeCache>,
+    pub history: RunHistory,
+    pub output_sizes: Option<OutputSizes>
+}
+
+impl Project {
+    pub fn new(registry: PipelineRegistry) -> Self {
+        Project {
+            registry,
+            dataset_types: DatasetTypes::new(),
+            cache: None,
+            history: RunHistory::new(DEFAULT_HISTORY_DIR),
+            output_sizes: None
+        }
+    }
+
+    pub fn with_dataset_types(mut self, dataset_types: DatasetTypes) -> Self {
+        self.dataset_types = dataset_types;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: NodeCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_history(mut self, history: RunHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Measures node outputs for run reports and `viz --run`.
+    pub fn with_output_sizes(mut self, sizes: OutputSizes) -> Self {
+        self.output_sizes = Some(sizes);
+        self
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "qupido", about = "List, run and inspect the pipelines of a qupido project")]
+pub struct Cli {
+    /// Directory with the `base` and environment configuration
+    #[arg(long, default_value = "conf", global = true)]
+    pub conf_dir: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Command
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the registered pipelines
+    List,
+    /// Run a pipeline, or a slice of it
+    Run(RunArgs),
+    /// Show the nodes, inputs and outputs of a pipeline
+    Describe {
+        #[arg(long, default_value = DEFAULT_PIPELINE)]
+        pipeline: String
+    },
+    /// Draw a pipeline
+    Viz {
+        #[arg(long, default_value = DEFAULT_PIPELINE)]
+        pipeline: String,
+        #[arg(long, value_enum, default_value_t = Format::Html)]
+        format: Format,
+        /// File to write to instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Show the durations and output sizes of this run in the HTML
+        #[arg(long)]
+        run: Option<Uuid>
+    },
+    /// Remove cached node outputs
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Remove all cached outputs
+    Clear,
+    /// Remove the cached outputs of the given nodes
+    Evict {
+        #[arg(required = true)]
+        nodes: Vec<String>
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Dot,
+    Mermaid,
+    Html
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[arg(long, default_value = DEFAULT_PIPELINE)]
+    pub pipeline: String,
+    /// Only run nodes with at least one of these tags
+    #[arg(long, value_delimiter = ',')]
+    pub tags: Vec<String>,
+    /// Only run these nodes and everything downstream of them
+    #[arg(long, value_delimiter = ',')]
+    pub from_nodes: Vec<String>,
+    /// Only run these nodes and everything they depend on
+    #[arg(long, value_delimiter = ',')]
+    pub to_nodes: Vec<String>,
+    /// Only run nodes downstream of these sources
+    #[arg(long, value_delimiter = ',')]
+    pub from_inputs: Vec<String>,
+    /// Only run nodes needed to produce these sources
+    #[arg(long, value_delimiter = ',')]
+    pub to_outputs: Vec<String>,
+    /// Configuration environment layered over `base`
+    #[arg(long)]
+    pub env: Option<String>,
+    #[arg(long, default_value_t = 1)]
+    pub workers: usize,
+    /// Parameter overrides such as `model.epochs=10`
+    #[arg(long, value_delimiter = ',')]
+    pub params: Vec<String>,
+    /// File to write the JSON run report to
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+    /// Run cached nodes again, replacing their cached outputs
+    #[arg(long)]
+    pub force: bool,
+    /// Continue a failed run from the nodes that didn't finish
+    #[arg(long)]
+    pub resume: Option<Uuid>
+}
+
+impl RunArgs {
+    /// Narrows `pipeline` down with the filters given, in the order tags,
+    /// nodes, inputs, outputs.
+    pub fn select(&self, pipeline: &Pipeline) -> QupidoResult<Pipeline> {
+        fn names(v: &[String]) -> Vec<&str> {
+            v.iter().map(|s| s.as_str()).collect()
+        }
+        fn sources(v: &[String]) -> Vec<Source> {
+            v.iter().map(id).collect()
+        }
+
+        let mut p = pipeline.clone();
+        if !self.tags.is_empty() {
+            p = p.only_nodes_with_tags(&names(&self.tags))?;
+        }
+        if !self.from_nodes.is_empty() {
+            p = p.from_nodes_named(&names(&self.from_nodes))?;
+        }
+        if !self.to_nodes.is_empty() {
+            p = p.to_nodes(&names(&self.to_nodes))?;
+        }
+        if !self.from_inputs.is_empty() {
+            p = p.from_inputs(&sources(&self.from_inputs))?;
+        }
+        if !self.to_outputs.is_empty() {
+            p = p.to_outputs(&sources(&self.to_outputs))?;
+        }
+        Ok(p)
+    }
+
+    /// The command line that resumes run `run_id` of these arguments.
+    pub fn resume_command(&self, conf_dir: &Path, run_id: Uuid) -> String {
+        let mut args = vec![
+            "qupido".to_string(),
+            "--conf-dir".to_string(),
+            conf_dir.display().to_string(),
+            "run".to_string(),
+            "--pipeline".to_string(),
+            self.pipeline.clone()
+        ];
+        let lists = [
+            ("--tags", &self.tags),
+            ("--from-nodes", &self.from_nodes),
+            ("--to-nodes", &self.to_nodes),
+            ("--from-inputs", &self.from_inputs),
+            ("--to-outputs", &self.to_outputs),
+            ("--params", &self.params)
+        ];
+        for (flag, values) in lists {
+            if !values.is_empty() {
+                args.extend([flag.to_string(), values.join(",")]);
+            }
+        }
+        if let Some(env) = &self.env {
+            args.extend(["--env".to_string(), env.clone()]);
+        }
+        if self.workers != 1 {
+            args.extend(["--workers".to_string(), self.workers.to_string()]);
+        }
+        if self.force {
+            args.push("--force".to_string());
+        }
+        args.extend(["--resume".to_string(), run_id.to_string()]);
+
+        args.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,@".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Parses the command line and executes it, printing errors with their
+/// causes to stderr.
+pub async fn main(project: Project) -> ExitCode {
+    let cli = Cli::parse();
+    match execute(&project, &cli, &mut io::stdout()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            let mut source = std::error::Error::source(&e);
+            while let Some(s) = source {
+                eprintln!("  caused by: {}", s);
+                source = s.source();
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub async fn execute(project: &Project, cli: &Cli, out: &mut dyn Write) -> QupidoResult {
+    match &cli.command {
+        Command::List => {
+            for name in project.registry.names() {
+                let pipeline = project.registry.get(name)?;
+                writeln!(out, "{} ({} nodes)", name, pipeline.nodes().len())?;
+            }
+        },
+        Command::Run(args) => {
+            let pipeline = args.select(project.registry.get(&args.pipeline)?)?;
+            let mut loader = ConfigLoader::new(&cli.conf_dir);
+            if let Some(env) = &args.env {
+                loader = loader.with_env(env);
+            }
+
+            let catalog = loader.catalog(&project.dataset_types)?;
+            let mut parameters = loader.parameters()?;
+            for p in &args.params {
+                let (key, value) = p.split_once('=')
+                    .ok_or_else(|| QupidoError::ConfigError(format!("parameter override '{}' is not key=value", p)))?;
+                parameters.set(key, serde_yaml::from_str(value)?);
+            }
+
+            // outputs worth keeping are saved through the catalog
+            let mut runner = Runner::parallel(args.workers)
+                .with_catalog(Arc::new(catalog))
+                .with_parameters(Arc::new(parameters))
+                .with_history(Arc::new(project.history.clone()))
+                .free_intermediates(&[]);
+            if let Some(cache) = &project.cache {
+                runner = runner.with_cache(Arc::new(cache.clone().force(args.force)));
+            }
+            if let Some(sizes) = &project.output_sizes {
+                runner = runner.with_output_sizes(Arc::new(sizes.clone()));
+            }
+            let (result, report) = match args.resume {
+                Some(run_id) => runner.resume_async(&pipeline, run_id, &Container::new()).await?,
+                None => runner.run_async_with_report(&pipeline, &Container::new()).await
+            };
+            if let Some(path) = &args.report {
+                std::fs::write(path, report.to_json()?)?;
+            }
+            if result.is_err() {
+                writeln!(out, "run {} failed, resume it with:\n  {}", report.run_id, args.resume_command(&cli.conf_dir, report.run_id))?;
+            }
+            result?;
+            match args.resume {
+                Some(run_id) => writeln!(out, "resumed run {} of pipeline '{}'", run_id, args.pipeline)?,
+                None => writeln!(out, "ran {} nodes of pipeline '{}'", pipeline.nodes().len(), args.pipeline)?
+            }
+        },
+        Command::Describe { pipeline: name } => {
+            let pipeline = project.registry.get(name)?;
+            let ids = |sources: Vec<Source>| sources.iter().map(|s| s.get_id()).collect::<Vec<_>>().join(", ");
+            writeln!(out, "pipeline '{}'", name)?;
+            for n in pipeline.nodes() {
+                writeln!(out, "  {}", n.name)?;
+                if let Some(ns) = &n.namespace {
+                    writeln!(out, "    namespace: {}", ns)?;
+                }
+                if !n.tags.is_empty() {
+                    let tags: Vec<_> = n.tags.iter().map(|t| match t {
+                        Tag::Tag(t) => t.as_str(),
+                    }).collect();
+                    writeln!(out, "    tags: {}", tags.join(", "))?;
+                }
+                writeln!(out, "    inputs: {}", ids(n.inputs.inputs()))?;
+                writeln!(out, "    outputs: {}", ids(n.outputs.outputs()))?;
+            }
+            writeln!(out, "inputs: {}", ids(pipeline.inputs()))?;
+            writeln!(out, "parameters: {}", ids(pipeline.parameters()))?;
+            writeln!(out, "outputs: {}", ids(pipeline.outputs()))?;
+        },
+        Command::Viz { pipeline: name, format, output, run } => {
+            let pipeline = project.registry.get(name)?;
+            let report = run.map(|run_id| project.history.load(run_id)).transpose()?;
+            let drawing = match format {
+                Format::Dot => pipeline.to_dot(),
+                Format::Mermaid => pipeline.to_mermaid(),
+                Format::Html => {
+                    let html = HtmlReport::new(pipeline).title(name.as_str());
+                    match &report {
+                        Some(report) => html.with_run_report(report),
+                        None => html
+                    }.render()
+                },
+            };
+            match output {
+                Some(path) => std::fs::write(path, drawing)?,
+                None => out.write_all(drawing.as_bytes())?,
+            }
+        },
+        Command::Cache { command } => {
+            let cache = project.cache.as_ref().ok_or_else(|| QupidoError::ConfigError("the project has no node cache".to_string()))?;
+            match command {
+                CacheCommand::Clear => {
+                    cache.clear()?;
+                    writeln!(out, "cleared {}", cache.dir().display())?;
+                },
+                CacheCommand::Evict { nodes } => {
+                    for node in nodes {
+                        writeln!(out, "evicted {} cache entries of '{}'", cache.evict(node)?, node)?;
+                    }
+                },
+            }
+        },
+    }
+
+    Ok(())
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_cli_commands() -> QupidoResult {
+    use futures::FutureExt;
+    use crate::{param, node::Node};
+
+    let scale = Node::new([id("x"), param("factor")], [id("y")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let factor: &i64 = ctx.inputs.get("params:factor")?;
+        if *factor == 0 {
+            return Err(QupidoError::external("factor must not be 0"));
+        }
+        let mut r = Container::new();
+        r.insert("y", x * factor)?;
+        Ok(r)
+    }).name("scale").tag("math").version("1");
+    let seed = Node::new((), [id("x")], |_| {
+        let mut r = Container::new();
+        r.insert("x", 7_i64)?;
+        Ok(r)
+    }).name("seed");
+    let registry = PipelineRegistry::new().with("math", Pipeline::from_nodes(&[seed, scale])?)?;
+    let conf_dir = std::env::temp_dir().join(format!("qupido_cli_{}", std::process::id()));
+    let project = Project::new(registry)
+        .with_cache(NodeCache::new(conf_dir.join("cache")))
+        .with_history(RunHistory::new(conf_dir.join("runs")))
+        .with_output_sizes(OutputSizes::new().with::<i64>(|v| futures::future::ready(Ok(v.unsigned_abs())).boxed()));
+    std::fs::create_dir_all(conf_dir.join("base"))?;
+    std::fs::write(conf_dir.join("base").join("parameters.yml"), "factor: 2\n")?;
+    let conf = conf_dir.to_string_lossy().to_string();
+
+    let execute_args = |args: &[&str]| -> QupidoResult<(QupidoResult, String)> {
+        let mut argv = vec!["qupido", "--conf-dir", conf.as_str()];
+        argv.extend_from_slice(args);
+        let cli = Cli::try_parse_from(argv).map_err(QupidoError::external)?;
+        let mut out = vec![];
+        let result = futures::executor::block_on(execute(&project, &cli, &mut out));
+        Ok((result, String::from_utf8(out).map_err(QupidoError::external)?))
+    };
+    let run = |args: &[&str]| -> QupidoResult<String> {
+        let (result, out) = execute_args(args)?;
+        result.map(|_| out)
+    };
+
+    assert_eq!(run(&["list"])?, "__default__ (2 nodes)\nmath (2 nodes)\n");
+    let report = conf_dir.join("report.json");
+    let report_arg = report.to_string_lossy().to_string();
+    assert_eq!(run(&["run", "--pipeline", "math", "--params", "factor=3", "--report", report_arg.as_str()])?, "ran 2 nodes of pipeline 'math'\n");
+    assert!(crate::report::RunReport::from_json(&std::fs::read_to_string(&report)?)?.succeeded());
+    assert!(matches!(run(&["run", "--pipeline", "math", "--tags", "math"]), Err(QupidoError::NodeFailed { node, .. }) if node == "scale"));
+    assert!(matches!(run(&["run", "--pipeline", "nope"]), Err(QupidoError::PipelineNotFound(name)) if name == "nope"));
+
+    assert_eq!(run(&["run"])?, "ran 2 nodes of pipeline '__default__'\n");
+    assert_eq!(run(&["run", "--force"])?, "ran 2 nodes of pipeline '__default__'\n");
+    assert_eq!(run(&["cache", "evict", "scale", "seed"])?, "evicted 2 cache entries of 'scale'\nevicted 0 cache entries of 'seed'\n");
+    assert!(run(&["cache", "clear"])?.starts_with("cleared "));
+
+    let (result, out) = execute_args(&["run", "--pipeline", "math", "--params", "factor=0", "--force"])?;
+    assert!(matches!(result, Err(QupidoError::NodeFailed { node, .. }) if node == "scale"));
+    let (_, command) = out.split_once("resume it with:\n  ").unwrap();
+    let run_id = command.trim_end().rsplit(' ').next().unwrap();
+    assert_eq!(command, format!("qupido --conf-dir {} run --pipeline math --params factor=0 --force --resume {}\n", conf, run_id));
+    assert_eq!(run(&["run", "--pipeline", "math", "--params", "factor=5", "--resume", run_id])?, format!("resumed run {} of pipeline 'math'\n", run_id));
+    let resumed = RunHistory::new(conf_dir.join("runs")).load(Uuid::parse_str(run_id).map_err(QupidoError::external)?)?;
+    assert!(resumed.succeeded());
+    assert!(matches!(run(&["run", "--resume", &Uuid::nil().to_string()]), Err(QupidoError::RunNotFound(_))));
+    assert_eq!(shell_quote("[x] -> [y]"), "'[x] -> [y]'");
+
+    let describe = run(&["describe", "--pipeline", "math"])?;
+    assert!(describe.contains("  scale\n    tags: math\n    inputs: x, params:factor\n    outputs: y\n"));
+    assert!(describe.ends_with("inputs: \nparameters: params:factor\noutputs: y\n"));
+    assert!(run(&["viz", "--pipeline", "math", "--format", "mermaid"])?.starts_with("flowchart LR\n"));
+    let viz = run(&["viz", "--pipeline", "math", "--run", run_id])?;
+    assert!(viz.contains("\"output_sizes\":{\"x\":7}"));
+
+    std::fs::remove_dir_all(&conf_dir)?;
+    Ok(())
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{catalog::{DataCatalog, Dataset, MemoryDataset}, parameters::Parameters, id, Source, QupidoResult, QupidoError};
+
+/// One entry of a `catalog.yml`, as written in the file.
+///
+/// ```yaml
+/// oscar_awards:
+///   type: csv
+///   path: ${data_dir}/01_raw/the_oscar_award.csv
+///   load_args:
+///     has_header: true
+///   credentials: warehouse
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub load_args: Map<String, Value>,
+    #[serde(default)]
+    pub save_args: Map<String, Value>,
+    #[serde(default)]
+    pub credentials: Option<String>
+}
+
+/// A catalog entry after templating and credential lookup, handed to the
+/// factory registered for its type.
+#[derive(Clone, Debug)]
+pub struct DatasetSpec {
+    pub source: Source,
+    pub kind: String,
+    pub path: Option<String>,
+    pub load_args: Map<String, Value>,
+    pub save_args: Map<String, Value>,
+    pub credentials: Map<String, Value>
+}
+
+impl DatasetSpec {
+    pub fn path(&self) -> QupidoResult<&str> {
+        self.path.as_deref().ok_or_else(|| QupidoError::ConfigError(format!("dataset '{}' of type '{}' needs a path", self.source.get_id(), self.kind)))
+    }
+
+    pub fn load_arg<T>(&self, key: &str) -> QupidoResult<Option<T>>
+        where T: DeserializeOwned
+    {
+        Self::arg(&self.source, &self.load_args, key)
+    }
+
+    pub fn save_arg<T>(&self, key: &str) -> QupidoResult<Option<T>>
+        where T: DeserializeOwned
+    {
+        Self::arg(&self.source, &self.save_args, key)
+    }
+
+    /// Fails with a `ConfigError` for load or save args other than the given
+    /// ones, so a dataset type doesn't silently ignore args it doesn't
+    /// understand. Factories call this before reading their args.
+    pub fn expect_args(&self, load_args: &[&str], save_args: &[&str]) -> QupidoResult {
+        for (kind, args, known) in [("load", &self.load_args, load_args), ("save", &self.save_args, save_args)] {
+            let mut unknown: Vec<_> = args.keys().filter(|k| !known.contains(&k.as_str())).map(|k| k.as_str()).collect();
+            if !unknown.is_empty() {
+                unknown.sort();
+                return Err(QupidoError::ConfigError(format!(
+                    "dataset '{}' of type '{}' doesn't understand the {} args [{}]",
+                    self.source.get_id(), self.kind, kind, unknown.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn arg<T>(source: &Source, args: &Map<String, Value>, key: &str) -> QupidoResult<Option<T>>
+        where T: DeserializeOwned
+    {
+        match args.get(key) {
+            Some(v) => serde_json::from_value(v.clone())
+                .map(Some)
+                .map_err(|e| QupidoError::ConfigError(format!("dataset '{}', argument '{}': {}", source.get_id(), key, e))),
+            None => Ok(None)
+        }
+    }
+}
+
+pub type DatasetFactory = dyn Fn(&DatasetSpec) -> QupidoResult<Arc<dyn Dataset>> + Send + Sync;
+
+/// Maps the `type` of a catalog entry to the code that builds its dataset.
+/// Only `memory` is known out of the box; crates providing datasets register
+/// their own types.
+#[derive(Clone)]
+pub struct DatasetTypes {
+    factories: HashMap<String, Arc<DatasetFactory>>
+}
+
+impl Default for DatasetTypes {
+    fn default() -> Self {
+        let mut types = DatasetTypes {
+            factories: HashMap::new()
+        };
+        types.register("memory", |spec| {
+            spec.expect_args(&[], &[])?;
+            Ok(Arc::new(MemoryDataset::new()))
+        });
+        types
+    }
+}
+
+impl DatasetTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F>(&mut self, kind: &str, factory: F)
+        where F: Fn(&DatasetSpec) -> QupidoResult<Arc<dyn Dataset>> + Send + Sync + 'static
+    {
+        self.factories.insert(kind.to_string(), Arc::new(factory));
+    }
+
+    pub fn build(&self, spec: &DatasetSpec) -> QupidoResult<Arc<dyn Dataset>> {
+        let factory = self.factories.get(&spec.kind)
+            .ok_or_else(|| QupidoError::ConfigError(format!("dataset '{}' has unknown type '{}'", spec.source.get_id(), spec.kind)))?;
+        factory(spec)
+    }
+}
+
+impl Debug for DatasetTypes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut kinds: Vec<_> = self.factories.keys().collect();
+        kinds.sort();
+        f.debug_struct("DatasetTypes").field("kinds", &kinds).finish()
+    }
+}
+
+/// Reads configuration files from a conf directory laid out per environment:
+///
+/// ```text
+/// conf/
+///   base/catalog.yml
+///   base/credentials.yml
+///   base/globals.yml
+///   prod/catalog.yml
+/// ```
+///
+/// Files of the selected environment are deep-merged over `base`. Each file
+/// may be YAML (`.yml`, `.yaml`) or TOML (`.toml`).
+#[derive(Clone, Debug)]
+pub struct ConfigLoader {
+    conf_dir: PathBuf,
+    env: Option<String>,
+    vars: HashMap<String, String>
+}
+
+pub const BASE_ENV: &str = "base";
+
+impl ConfigLoader {
+    pub fn new(conf_dir: impl Into<PathBuf>) -> Self {
+        ConfigLoader {
+            conf_dir: conf_dir.into(),
+            env: None,
+            vars: HashMap::new()
+        }
+    }
+
+    pub fn with_env(mut self, env: impl Into<String>) -> Self {
+        self.env = Some(env.into());
+        self
+    }
+
+    /// Sets a template variable, taking precedence over `globals` and the
+    /// process environment.
+    pub fn with_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn env(&self) -> &str {
+        self.env.as_deref().unwrap_or(BASE_ENV)
+    }
+
+    /// Loads the config called `name` (e.g. `catalog`), merging the selected
+    /// environment over `base`. Missing files count as empty.
+    pub fn get(&self, name: &str) -> QupidoResult<Value> {
+        let mut merged = Value::Object(Map::new());
+        let mut envs = vec![BASE_ENV];
+        if let Some(env) = &self.env {
+            if env != BASE_ENV {
+                envs.push(env);
+            }
+        }
+
+        for env in envs {
+            for ext in ["yml", "yaml", "toml"] {
+                let path = self.conf_dir.join(env).join(format!("{}.{}", name, ext));
+                if path.exists() {
+                    merge(&mut merged, read_file(&path)?);
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Template variables: the environment name as `env`, flattened
+    /// `globals`, and variables set with [`ConfigLoader::with_var`].
+    pub fn variables(&self) -> QupidoResult<HashMap<String, String>> {
+        let mut vars = HashMap::new();
+        flatten_into(&self.get("globals")?, "", &mut vars);
+        vars.insert("env".to_string(), self.env().to_string());
+        vars.extend(self.vars.clone());
+        Ok(vars)
+    }
+
+    /// Loads `parameters`, with `${...}` placeholders resolved.
+    pub fn parameters(&self) -> QupidoResult<Parameters> {
+        let vars = self.variables()?;
+        Parameters::from_value(template(self.get("parameters")?, &vars)?)
+    }
+
+    /// Builds a catalog out of `catalog` and `credentials`, with `${...}`
+    /// placeholders resolved.
+    pub fn catalog(&self, types: &DatasetTypes) -> QupidoResult<DataCatalog> {
+        let vars = self.variables()?;
+        let catalog = template(self.get("catalog")?, &vars)?;
+        let credentials = template(self.get("credentials")?, &vars)?;
+        catalog_from_value(&catalog, &credentials, types)
+    }
+}
+
+/// Builds a catalog out of an already parsed catalog config. `credentials`
+/// maps the names used in `credentials:` entries to their values.
+pub fn catalog_from_value(catalog: &Value, credentials: &Value, types: &DatasetTypes) -> QupidoResult<DataCatalog> {
+    let entries = catalog.as_object().ok_or_else(|| QupidoError::ConfigError("catalog config must be a mapping".to_string()))?;
+
+    let mut keys: Vec<_> = entries.keys().collect();
+    keys.sort();
+
+    let mut r = DataCatalog::new();
+    for key in keys {
+        let config: DatasetConfig = serde_json::from_value(entries[key].clone())
+            .map_err(|e| QupidoError::ConfigError(format!("dataset '{}': {}", key, e)))?;
+
+        let credentials = match &config.credentials {
+            Some(name) => credentials.get(name)
+                .and_then(|c| c.as_object())
+                .cloned()
+                .ok_or_else(|| QupidoError::ConfigError(format!("dataset '{}' refers to missing credentials '{}'", key, name)))?,
+            None => Map::new()
+        };
+
+        let spec = DatasetSpec {
+            source: id(key.as_str()),
+            kind: config.kind,
+            path: config.path,
+            load_args: config.load_args,
+            save_args: config.save_args,
+            credentials
+        };
+        let dataset = types.build(&spec)?;
+        r.add_shared(spec.source, dataset)?;
+    }
+
+    Ok(r)
+}
+
+pub fn read_file(path: &Path) -> QupidoResult<Value> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| QupidoError::ConfigError(format!("{}: {}", path.display(), e)))?;
+
+    let parsed = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str::<Value>(&content).map_err(|e| e.to_string()),
+        _ => serde_yaml::from_str::<Option<Value>>(&content)
+            .map(|v| v.unwrap_or(Value::Object(Map::new())))
+            .map_err(|e| e.to_string()),
+    };
+
+    parsed.map_err(|e| QupidoError::ConfigError(format!("{}: {}", path.display(), e)))
+}
+
+/// Deep-merges `overlay` into `base`: mappings are merged key by key,
+/// everything else is replaced.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (k, v) in overlay {
+                match base.get_mut(&k) {
+                    Some(existing) => merge(existing, v),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Replaces `${name}` placeholders in every string of `value`. Names are
+/// looked up in `vars` first and then in the process environment.
+pub fn template(value: Value, vars: &HashMap<String, String>) -> QupidoResult<Value> {
+    Ok(match value {
+        Value::String(s) => Value::String(template_str(&s, vars)?),
+        Value::Array(a) => Value::Array(a.into_iter().map(|v| template(v, vars)).collect::<QupidoResult<_>>()?),
+        Value::Object(o) => {
+            let mut r = Map::new();
+            for (k, v) in o {
+                r.insert(k, template(v, vars)?);
+            }
+            Value::Object(r)
+        },
+        v => v,
+    })
+}
+
+fn template_str(s: &str, vars: &HashMap<String, String>) -> QupidoResult<String> {
+    let mut r = String::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find("${") {
+        r.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| QupidoError::ConfigError(format!("unterminated placeholder in '{}'", s)))?;
+        let name = &rest[start + 2..start + end];
+
+        let value = vars.get(name).cloned()
+            .or_else(|| std::env::var(name).ok())
+            .ok_or_else(|| QupidoError::ConfigError(format!("unknown variable '{}' in '{}'", name, s)))?;
+        r.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    r.push_str(rest);
+
+    Ok(r)
+}
+
+/// Flattens nested mappings into dotted keys, e.g. `paths.raw`.
+pub(crate) fn flatten_into(value: &Value, prefix: &str, into: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(o) => {
+            for (k, v) in o {
+                let key = if prefix.is_empty() { k.clone() } else { format!("{}.{}", prefix, k) };
+                flatten_into(v, &key, into);
+            }
+        },
+        Value::String(s) => {
+            into.insert(prefix.to_string(), s.clone());
+        },
+        Value::Null => (),
+        v => {
+            into.insert(prefix.to_string(), v.to_string());
+        },
+    }
+}
+
+
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+fn test_conf_dir(files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qupido_conf_{}", uuid::Uuid::new_v4()));
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_catalog_from_yaml_and_toml() -> QupidoResult {
+    use std::sync::Mutex;
+
+    let conf = test_conf_dir(&[
+        ("base/globals.yml", "data_dir: /data\nwarehouse:\n  schema: analytics\n"),
+        ("base/catalog.yml", r#"
+oscar_awards:
+  type: file
+  path: ${data_dir}/${env}/oscars.csv
+  load_args:
+    has_header: true
+scratch:
+  type: memory
+"#),
+        ("base/credentials.yml", "warehouse:\n  user: reader\n  schema: ${warehouse.schema}\n"),
+        ("prod/catalog.toml", r#"
+[oscar_awards]
+credentials = "warehouse"
+
+[best_pictures]
+type = "file"
+path = "${data_dir}/${env}/best_pictures.parquet"
+"#),
+    ]);
+
+    let specs = Arc::new(Mutex::new(vec![]));
+    let mut types = DatasetTypes::new();
+    {
+        let specs = specs.clone();
+        types.register("file", move |spec| {
+            specs.lock().unwrap().push(spec.clone());
+            Ok(Arc::new(MemoryDataset::new()))
+        });
+    }
+
+    let catalog = ConfigLoader::new(&conf).catalog(&types)?;
+    assert_eq!(catalog.sources(), vec![id("oscar_awards"), id("scratch")]);
+    {
+        let specs = specs.lock().unwrap();
+        assert_eq!(specs[0].path()?, "/data/base/oscars.csv");
+        assert_eq!(specs[0].load_arg::<bool>("has_header")?, Some(true));
+        assert!(specs[0].credentials.is_empty());
+    }
+
+    specs.lock().unwrap().clear();
+    let catalog = ConfigLoader::new(&conf).with_env("prod").catalog(&types)?;
+    assert_eq!(catalog.sources(), vec![id("best_pictures"), id("oscar_awards"), id("scratch")]);
+    {
+        let specs = specs.lock().unwrap();
+        assert_eq!(specs[0].path()?, "/data/prod/best_pictures.parquet");
+        assert_eq!(specs[1].path()?, "/data/prod/oscars.csv");
+        assert_eq!(specs[1].credentials.get("user"), Some(&Value::String("reader".to_string())));
+        assert_eq!(specs[1].credentials.get("schema"), Some(&Value::String("analytics".to_string())));
+    }
+
+    let catalog = ConfigLoader::new(&conf).with_var("data_dir", "/tmp").catalog(&types)?;
+    assert_eq!(catalog.sources().len(), 2);
+    assert_eq!(specs.lock().unwrap().last().unwrap().path()?, "/tmp/base/oscars.csv");
+
+    fs::remove_dir_all(conf).unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_parameters_with_env_overlay() -> QupidoResult {
+    let conf = test_conf_dir(&[
+        ("base/parameters.yml", "model:\n  learning_rate: 0.1\n  epochs: 10\nregion: eu\n"),
+        ("prod/parameters.toml", "[model]\nepochs = 50\n"),
+        ("prod/globals.yml", "tag: v2\n"),
+        ("prod/parameters.yml", "label: ${tag}-${env}\n"),
+    ]);
+
+    let parameters = ConfigLoader::new(&conf).parameters()?;
+    assert_eq!(parameters.get_as::<i64>("model.epochs")?, 10);
+
+    let parameters = ConfigLoader::new(&conf).with_env("prod").parameters()?;
+    assert_eq!(parameters.get_as::<i64>("model.epochs")?, 50);
+    assert_eq!(parameters.get_as::<f64>("model.learning_rate")?, 0.1);
+    assert_eq!(parameters.get_as::<String>("label")?, "v2-prod");
+    assert_eq!(parameters.keys(), vec!["label", "model.epochs", "model.learning_rate", "region"]);
+
+    fs::remove_dir_all(conf).unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_catalog_config_errors() {
+    let conf = test_conf_dir(&[
+        ("base/catalog.yml", "a:\n  type: nope\n"),
+        ("broken/catalog.yml", "a:\n  type: memory\n  path: ${missing_variable_for_test}\n"),
+        ("creds/catalog.yml", "a:\n  type: memory\n  credentials: nobody\n"),
+        ("args/catalog.yml", "a:\n  type: memory\n  save_args:\n    mode: overwrite\n"),
+    ]);
+    let types = DatasetTypes::new();
+
+    for env in ["base", "broken", "creds", "args"] {
+        match ConfigLoader::new(&conf).with_env(env).catalog(&types) {
+            Err(QupidoError::ConfigError(_)) => (),
+            other => panic!("expected a config error for {}, got {:?}", env, other.map(|c| c.sources())),
+        }
+    }
+
+    fs::remove_dir_all(conf).unwrap();
+}
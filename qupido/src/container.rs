@@ -2,31 +2,83 @@ use std::any::TypeId;
 use std::{collections::HashMap, sync::Arc, any::Any};
 use std::fmt::Debug;
 
-use crate::{QupidoResult, QupidoError};
+use crate::{parameters::WholeNumber, source::SourceKey, QupidoResult, QupidoError};
 
-#[derive(Clone, Debug)]
-pub struct Container<T> {
-    pub data: HashMap<String, Arc<T>>
+/// Holds the values flowing through a pipeline, keyed by source id. Values
+/// of different types can be mixed; they are downcast again on [`Container::get`].
+#[derive(Clone, Debug, Default)]
+pub struct Container {
+    pub data: HashMap<String, Arc<dyn ContainerData>>
 }
 
-pub trait ContainerData: Any + Debug {
+pub trait ContainerData: Any + Debug + Send + Sync {
     fn as_any(&self) -> &dyn Any;
-    fn as_any_mut(&mut self) -> &mut dyn Any;    
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn data_type(&self) -> DataType;
 }
 
-impl<T> ContainerData for T where T: Any + Debug {
+impl<T> ContainerData for T where T: Any + Debug + Send + Sync {
     fn as_any(&self) -> &dyn Any { self }
     fn as_any_mut(&mut self) -> &mut dyn Any { self }
+    fn data_type(&self) -> DataType { DataType::of::<T>() }
 }
 
-impl<T> Container<T> {
+/// The type of a value, as nodes declare it for their inputs and outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DataType {
+    pub id: TypeId,
+    pub name: &'static str
+}
+
+impl DataType {
+    pub fn of<T>() -> Self
+        where T: Any
+    {
+        DataType {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>()
+        }
+    }
+}
+
+impl dyn ContainerData {
+    /// The value as `T`. Whole number parameters are returned for both `i64`
+    /// and `f64`, see [`WholeNumber`].
+    pub fn downcast_ref<T>(&self) -> Option<&T>
+        where T: Any
+    {
+        let any = self.as_any();
+        any.downcast_ref::<T>()
+            .or_else(|| any.downcast_ref::<WholeNumber>().and_then(|n| n.read::<T>()))
+    }
+
+    /// Whether [`downcast_ref`](Self::downcast_ref) returns the value as type `t`.
+    pub fn reads_as(&self, t: &DataType) -> bool {
+        t.id == self.as_any().type_id() || (self.as_any().is::<WholeNumber>() && WholeNumber::reads_as(t))
+    }
+
+    /// Like [`downcast_ref`](Self::downcast_ref), but reports a
+    /// `DataTypeMismatch` for `id` when the stored type differs.
+    pub fn try_downcast_ref<T>(&self, id: &str) -> QupidoResult<&T>
+        where T: Any
+    {
+        self.downcast_ref::<T>().ok_or_else(|| QupidoError::DataTypeMismatch {
+            id: id.to_string(),
+            requested: DataType::of::<T>(),
+            stored: self.data_type()
+        })
+    }
+}
+
+impl Container {
     pub fn new() -> Self {
         Container {
             data: HashMap::default()
         }
     }
 
-    pub fn insert(&mut self, key: &str, value: T) -> QupidoResult
+    pub fn insert<T>(&mut self, key: &str, value: T) -> QupidoResult
+        where T: ContainerData
     {
         if self.data.contains_key(key) {
             return Err(QupidoError::DuplicateData(key.to_string()));
@@ -37,14 +89,68 @@ impl<T> Container<T> {
         Ok(())
     }
 
-    pub fn upsert(&mut self, key: &str, value: T)
+    pub fn upsert<T>(&mut self, key: &str, value: T)
+        where T: ContainerData
     {
         self.data.insert(key.to_string(), Arc::new(value));
     }
 
-    pub fn get(&self, key: &str) -> QupidoResult<&T>
+    pub fn get<T>(&self, key: &str) -> QupidoResult<&T>
+        where T: Any
     {
         let v = self.data.get(key).ok_or(QupidoError::DataNotFound(key.to_string()))?;
-        Ok(v.as_ref())
+        // deref the Arc first, the Arc itself is ContainerData as well
+        v.as_ref().try_downcast_ref::<T>(key)
     }
-}
\ No newline at end of file
+
+    /// Like [`Container::get`] with the type taken from the key.
+    pub fn get_key<U>(&self, key: &SourceKey<U>) -> QupidoResult<&U>
+        where U: Any
+    {
+        self.get(key.id())
+    }
+
+    pub fn insert_key<U>(&mut self, key: &SourceKey<U>, value: U) -> QupidoResult
+        where U: ContainerData
+    {
+        self.insert(key.id(), value)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+}
+
+
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_mixed_types() -> QupidoResult {
+    let mut c = Container::new();
+    c.insert("count", 3_u32)?;
+    c.insert("name", "oscars".to_string())?;
+    c.insert("weights", vec![0.5_f64, 1.5])?;
+
+    assert_eq!(*c.get::<u32>("count")?, 3);
+    assert_eq!(c.get::<String>("name")?, "oscars");
+    assert_eq!(c.get::<Vec<f64>>("weights")?.len(), 2);
+
+    match c.get::<i64>("count") {
+        Err(QupidoError::DataTypeMismatch { id, requested, stored }) => {
+            assert_eq!(id, "count");
+            assert_eq!(requested, DataType::of::<i64>());
+            assert_eq!(stored, DataType::of::<u32>());
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(c.get::<i64>("count").unwrap_err().to_string(), "data 'count' was requested as i64 but holds u32");
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::{pipeline::Pipeline, node::Node, Tag};
+
+// ColorBrewer Set3, assigned to tags in alphabetical order
+const TAG_COLORS: [&str; 8] = ["#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69", "#fccde5"];
+
+/// A pipeline laid out for drawing. Nodes and datasets are both vertices:
+/// datasets point to the nodes consuming them, nodes to the datasets they
+/// produce, and every edge is labeled with the id the node uses.
+pub(crate) struct Diagram<'p> {
+    pub(crate) nodes: &'p [Node],
+    pub(crate) datasets: Vec<String>,
+    pub(crate) edges: Vec<(String, String, String)>,
+    pub(crate) colors: BTreeMap<String, &'static str>
+}
+
+impl<'p> Diagram<'p> {
+    pub(crate) fn new(pipeline: &'p Pipeline) -> Self {
+        let nodes = pipeline.nodes();
+        let datasets: BTreeSet<_> = nodes.iter()
+            .flat_map(|n| n.inputs.inputs().into_iter().chain(n.outputs.outputs()))
+            .map(|s| s.get_id())
+            .collect();
+        let datasets: Vec<_> = datasets.into_iter().collect();
+        let dataset = |id: &str| format!("d{}", datasets.iter().position(|d| d == id).expect("all datasets are listed"));
+
+        let mut edges = vec![];
+        for (i, n) in nodes.iter().enumerate() {
+            for (local, global) in n.inputs.pairs() {
+                edges.push((dataset(&global.get_id()), format!("n{}", i), local.get_id()));
+            }
+            for (local, global) in n.outputs.pairs() {
+                edges.push((format!("n{}", i), dataset(&global.get_id()), local.get_id()));
+            }
+        }
+
+        let tags: BTreeSet<_> = nodes.iter()
+            .flat_map(|n| n.tags.iter().map(|t| match t {
+                Tag::Tag(t) => t.clone(),
+            }))
+            .collect();
+        let colors = tags.into_iter().zip(TAG_COLORS.iter().cycle().copied()).collect();
+
+        Diagram {
+            nodes,
+            datasets,
+            edges,
+            colors
+        }
+    }
+
+    /// Indices of the nodes grouped by namespace, nodes without one first.
+    pub(crate) fn namespaces(&self) -> BTreeMap<Option<&'p str>, Vec<usize>> {
+        let mut r: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (i, n) in self.nodes.iter().enumerate() {
+            r.entry(n.namespace.as_deref()).or_default().push(i);
+        }
+        r
+    }
+
+    pub(crate) fn color(&self, node: &Node) -> Option<&'static str> {
+        node.tags.first().map(|t| match t {
+            Tag::Tag(t) => self.colors[t],
+        })
+    }
+}
+
+impl Pipeline {
+    /// Draws the pipeline in Graphviz DOT. Nodes are boxes, clustered by
+    /// namespace and filled by their first tag; datasets are ellipses.
+    pub fn to_dot(&self) -> String {
+        let d = Diagram::new(self);
+        let mut r = String::from("digraph pipeline {\n    rankdir=LR;\n");
+
+        for (i, id) in d.datasets.iter().enumerate() {
+            writeln!(r, "    d{} [label=\"{}\", shape=ellipse];", i, dot_escape(id)).unwrap();
+        }
+        for (c, (namespace, nodes)) in d.namespaces().into_iter().enumerate() {
+            let indent = match namespace {
+                Some(ns) => {
+                    writeln!(r, "    subgraph cluster_{} {{\n        label=\"{}\";", c, dot_escape(ns)).unwrap();
+                    "        "
+                },
+                None => "    "
+            };
+            for i in nodes {
+                let n = &d.nodes[i];
+                let style = match d.color(n) {
+                    Some(color) => format!(", style=filled, fillcolor=\"{}\"", color),
+                    None => String::new()
+                };
+                writeln!(r, "{}n{} [label=\"{}\", shape=box{}];", indent, i, dot_escape(&n.name), style).unwrap();
+            }
+            if namespace.is_some() {
+                r.push_str("    }\n");
+            }
+        }
+        for (from, to, label) in &d.edges {
+            writeln!(r, "    {} -> {} [label=\"{}\"];", from, to, dot_escape(label)).unwrap();
+        }
+
+        r.push_str("}\n");
+        r
+    }
+
+    /// Draws the pipeline as a Mermaid flowchart, laid out like [`Pipeline::to_dot`]
+    /// with namespaces as subgraphs and datasets as cylinders.
+    pub fn to_mermaid(&self) -> String {
+        let d = Diagram::new(self);
+        let mut r = String::from("flowchart LR\n");
+
+        for (i, id) in d.datasets.iter().enumerate() {
+            writeln!(r, "    d{}[(\"{}\")]", i, mermaid_escape(id)).unwrap();
+        }
+        for (c, (namespace, nodes)) in d.namespaces().into_iter().enumerate() {
+            let indent = match namespace {
+                Some(ns) => {
+                    writeln!(r, "    subgraph ns{} [\"{}\"]", c, mermaid_escape(ns)).unwrap();
+                    "        "
+                },
+                None => "    "
+            };
+            for i in nodes {
+                writeln!(r, "{}n{}[\"{}\"]", indent, i, mermaid_escape(&d.nodes[i].name)).unwrap();
+            }
+            if namespace.is_some() {
+                r.push_str("    end\n");
+            }
+        }
+        for (from, to, label) in &d.edges {
+            writeln!(r, "    {} -->|\"{}\"| {}", from, mermaid_escape(label), to).unwrap();
+        }
+
+        let tags: Vec<_> = d.colors.keys().collect();
+        for (t, tag) in tags.iter().enumerate() {
+            writeln!(r, "    classDef tag{} fill:{}", t, d.colors[*tag]).unwrap();
+        }
+        for (i, n) in d.nodes.iter().enumerate() {
+            if let Some(Tag::Tag(tag)) = n.tags.first() {
+                let t = tags.iter().position(|x| *x == tag).expect("all tags have a color");
+                writeln!(r, "    class n{} tag{}", i, t).unwrap();
+            }
+        }
+
+        r
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_dot_and_mermaid() -> crate::QupidoResult {
+    use crate::{id, container::Container};
+
+    let clean = Node::new([id("raw")], [id("eu.clean")], |_| Ok(Container::new())).name("cleaning").tag("ingest");
+    let train = Node::new([(id("data"), id("clean")), (id("lr"), id("params:lr"))], [id("model")], |_| Ok(Container::new()))
+        .name("train")
+        .tag("ml");
+    let pipeline = Pipeline::from_nodes(&[clean])?.add(&Pipeline::from_nodes(&[train])?.with_namespace("eu")?)?;
+
+    let dot = pipeline.to_dot();
+    assert!(dot.starts_with("digraph pipeline {\n"));
+    assert!(dot.contains("    d0 [label=\"eu.clean\", shape=ellipse];\n"));
+    assert!(dot.contains("    n0 [label=\"cleaning\", shape=box, style=filled, fillcolor=\"#8dd3c7\"];\n"));
+    assert!(dot.contains("    subgraph cluster_1 {\n        label=\"eu\";\n        n1 [label=\"eu.train\", shape=box, style=filled, fillcolor=\"#ffffb3\"];\n    }\n"));
+    assert!(dot.contains("    d0 -> n1 [label=\"data\"];\n"));
+    assert!(dot.contains("    d2 -> n1 [label=\"lr\"];\n"));
+    assert!(dot.contains("    n1 -> d1 [label=\"model\"];\n"));
+
+    let mermaid = pipeline.to_mermaid();
+    assert!(mermaid.starts_with("flowchart LR\n"));
+    assert!(mermaid.contains("    d0[(\"eu.clean\")]\n"));
+    assert!(mermaid.contains("    subgraph ns1 [\"eu\"]\n        n1[\"eu.train\"]\n    end\n"));
+    assert!(mermaid.contains("    d3 -->|\"raw\"| n0\n"));
+    assert!(mermaid.contains("    n0 -->|\"eu.clean\"| d0\n"));
+    assert!(mermaid.contains("    classDef tag1 fill:#ffffb3\n    class n0 tag0\n    class n1 tag1\n"));
+
+    Ok(())
+}
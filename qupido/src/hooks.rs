@@ -0,0 +1,177 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{container::Container, node::Node, pipeline::Pipeline, Source, QupidoResult, QupidoError};
+
+/// Code the runner calls around a pipeline run and each of its nodes, for
+/// timing, data validation, lineage or alerting without touching the nodes.
+///
+/// Every method does nothing by default. An error returned from
+/// `before_node_run` or `after_node_run` fails the node like an error of the
+/// node itself. With several workers, node hooks are called from the thread
+/// running the node.
+pub trait Hook: Debug + Send + Sync {
+    fn before_pipeline_run(&self, _pipeline: &Pipeline) {}
+
+    /// `inputs` is what the node is about to be called with.
+    fn before_node_run(&self, _node: &Node, _inputs: &Container) -> QupidoResult {
+        Ok(())
+    }
+
+    /// `outputs` is what the node returned, keyed by the ids the node uses.
+    fn after_node_run(&self, _node: &Node, _outputs: &Container, _duration: Duration) -> QupidoResult {
+        Ok(())
+    }
+
+    /// Called once for the error a node failed with, after node context was
+    /// attached to it.
+    fn on_node_error(&self, _node: &Node, _error: &QupidoError) {}
+
+    /// Called for every pipeline input loaded from the catalog.
+    fn after_dataset_loaded(&self, _source: &Source) {}
+
+    /// Called for every node output saved to its persistent dataset.
+    fn after_dataset_saved(&self, _node: &Node, _source: &Source) {}
+
+    /// Called after every node succeeded, with the final run state. Failed
+    /// runs call `on_pipeline_error` instead.
+    fn after_pipeline_run(&self, _pipeline: &Pipeline, _state: &Container) {}
+
+    /// Called once for the error a run failed with, whether a node failed
+    /// or the run didn't get to start one.
+    fn on_pipeline_error(&self, _pipeline: &Pipeline, _error: &QupidoError) {}
+}
+
+impl<H> Hook for Arc<H> where H: Hook + ?Sized {
+    fn before_pipeline_run(&self, pipeline: &Pipeline) {
+        self.as_ref().before_pipeline_run(pipeline)
+    }
+
+    fn before_node_run(&self, node: &Node, inputs: &Container) -> QupidoResult {
+        self.as_ref().before_node_run(node, inputs)
+    }
+
+    fn after_node_run(&self, node: &Node, outputs: &Container, duration: Duration) -> QupidoResult {
+        self.as_ref().after_node_run(node, outputs, duration)
+    }
+
+    fn on_node_error(&self, node: &Node, error: &QupidoError) {
+        self.as_ref().on_node_error(node, error)
+    }
+
+    fn after_dataset_loaded(&self, source: &Source) {
+        self.as_ref().after_dataset_loaded(source)
+    }
+
+    fn after_dataset_saved(&self, node: &Node, source: &Source) {
+        self.as_ref().after_dataset_saved(node, source)
+    }
+
+    fn after_pipeline_run(&self, pipeline: &Pipeline, state: &Container) {
+        self.as_ref().after_pipeline_run(pipeline, state)
+    }
+
+    fn on_pipeline_error(&self, pipeline: &Pipeline, error: &QupidoError) {
+        self.as_ref().on_pipeline_error(pipeline, error)
+    }
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct RecordingHook {
+    events: std::sync::Mutex<Vec<String>>
+}
+
+#[cfg(test)]
+impl Hook for RecordingHook {
+    fn before_pipeline_run(&self, pipeline: &Pipeline) {
+        self.events.lock().unwrap().push(format!("start {}", pipeline.nodes().len()));
+    }
+
+    fn before_node_run(&self, node: &Node, inputs: &Container) -> QupidoResult {
+        if let Ok(x) = inputs.get::<i64>("x") {
+            if *x < 0 {
+                return Err(QupidoError::external("x must not be negative"));
+            }
+        }
+        self.events.lock().unwrap().push(format!("before {}", node.name));
+        Ok(())
+    }
+
+    fn after_node_run(&self, node: &Node, outputs: &Container, _duration: Duration) -> QupidoResult {
+        let mut keys: Vec<_> = outputs.data.keys().cloned().collect();
+        keys.sort();
+        self.events.lock().unwrap().push(format!("after {} {}", node.name, keys.join(",")));
+        Ok(())
+    }
+
+    fn on_node_error(&self, node: &Node, error: &QupidoError) {
+        let cause = std::error::Error::source(error).map(|e| e.to_string()).unwrap_or_default();
+        self.events.lock().unwrap().push(format!("error {} {}", node.name, cause));
+    }
+
+    fn after_pipeline_run(&self, _pipeline: &Pipeline, state: &Container) {
+        self.events.lock().unwrap().push(format!("done {}", state.data.len()));
+    }
+
+    fn on_pipeline_error(&self, _pipeline: &Pipeline, error: &QupidoError) {
+        self.events.lock().unwrap().push(format!("failed {}", error));
+    }
+}
+
+#[test]
+fn test_hooks_around_nodes() -> QupidoResult {
+    use crate::{id, runner::Runner};
+
+    let double = Node::new([id("x")], [id("doubled")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let mut r = Container::new();
+        r.insert("doubled", x * 2)?;
+        Ok(r)
+    }).name("double");
+    let plus_one = Node::new_async([id("doubled")], [id("result")], |ctx| async move {
+        let v: &i64 = ctx.inputs.get("doubled")?;
+        let mut r = Container::new();
+        r.insert("result", v + 1)?;
+        Ok(r)
+    }).name("plus_one");
+    let pipeline = Pipeline::from_nodes(std::slice::from_ref(&double))?;
+    let async_pipeline = Pipeline::from_nodes(&[double, plus_one])?;
+
+    let mut container = Container::new();
+    container.insert("x", 4_i64)?;
+
+    for workers in [1, 2] {
+        let hook = Arc::new(RecordingHook::default());
+        Runner::parallel(workers).with_hook(hook.clone()).run(&pipeline, &container)?;
+        assert_eq!(*hook.events.lock().unwrap(), vec!["start 1", "before double", "after double doubled", "done 2"]);
+    }
+
+    let hook = Arc::new(RecordingHook::default());
+    futures::executor::block_on(Runner::sequential().with_hook(hook.clone()).run_async(&async_pipeline, &container))?;
+    assert_eq!(*hook.events.lock().unwrap(), vec![
+        "start 2", "before double", "after double doubled", "before plus_one", "after plus_one result", "done 3"
+    ]);
+
+    let mut negative = Container::new();
+    negative.insert("x", -1_i64)?;
+    let hook = Arc::new(RecordingHook::default());
+    let result = Runner::sequential().with_hook(hook.clone()).run(&pipeline, &negative);
+    assert!(matches!(result, Err(QupidoError::NodeFailed { node, .. }) if node == "double"));
+    assert_eq!(*hook.events.lock().unwrap(), vec![
+        "start 1",
+        "error double x must not be negative",
+        "failed node 'double' with inputs [x] failed: x must not be negative"
+    ]);
+
+    Ok(())
+}
@@ -0,0 +1,370 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::{diagram::Diagram, pipeline::Pipeline, Tag, QupidoResult};
+
+const COLUMN_WIDTH: usize = 200;
+const ROW_HEIGHT: usize = 60;
+const BOX_WIDTH: usize = 160;
+const BOX_HEIGHT: usize = 34;
+const MARGIN: usize = 30;
+
+/// What happened to a node in a completed run, shown next to it in an
+/// [`HtmlReport`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeRun {
+    pub duration: Duration,
+    /// Size of every output the node produced, in whatever unit fits the
+    /// data, e.g. rows for DataFrames.
+    pub output_sizes: BTreeMap<String, u64>
+}
+
+/// A self-contained HTML page drawing a pipeline, in the spirit of
+/// kedro-viz but static: no server and no external scripts, so it can be
+/// attached to CI runs as is.
+///
+/// Clicking a node or dataset shows its namespace, tags, inputs and outputs,
+/// and, if run information was added, durations and output sizes. Namespaces
+/// and tags in the sidebar highlight the nodes they contain.
+pub struct HtmlReport<'p> {
+    pipeline: &'p Pipeline,
+    title: String,
+    runs: HashMap<String, NodeRun>
+}
+
+impl<'p> HtmlReport<'p> {
+    pub fn new(pipeline: &'p Pipeline) -> Self {
+        HtmlReport {
+            pipeline,
+            title: "pipeline".to_string(),
+            runs: HashMap::new()
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Adds run information for the node named `node`.
+    pub fn with_node_run(mut self, node: impl Into<String>, run: NodeRun) -> Self {
+        self.runs.insert(node.into(), run);
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let d = Diagram::new(self.pipeline);
+        let positions = layout(&d);
+
+        fill(PAGE, &[
+            ("TITLE", &escape(&self.title)),
+            ("SVG", &svg(&d, &positions)),
+            ("DATA", &self.data(&d).to_string().replace("</", "<\\/"))
+        ])
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> QupidoResult {
+        std::fs::write(path, self.render())?;
+        Ok(())
+    }
+
+    fn data(&self, d: &Diagram) -> Value {
+        let nodes: Vec<_> = d.nodes.iter().enumerate().map(|(i, n)| {
+            let vertex = format!("n{}", i);
+            let sources = |from_node: bool| -> Vec<Value> {
+                d.edges.iter()
+                    .filter(|(from, to, _)| if from_node { *from == vertex } else { *to == vertex })
+                    .map(|(from, to, local)| {
+                        let dataset = if from_node { to } else { from };
+                        json!({ "local": local, "dataset": dataset })
+                    })
+                    .collect()
+            };
+            let tags: Vec<_> = n.tags.iter().map(|t| match t {
+                Tag::Tag(t) => t.clone(),
+            }).collect();
+            let run = self.runs.get(&n.name).map(|r| json!({
+                "duration_ms": r.duration.as_secs_f64() * 1000.0,
+                "output_sizes": r.output_sizes
+            }));
+
+            json!({
+                "id": vertex,
+                "name": n.name,
+                "namespace": n.namespace,
+                "tags": tags,
+                "inputs": sources(false),
+                "outputs": sources(true),
+                "run": run
+            })
+        }).collect();
+
+        let datasets: Vec<_> = d.datasets.iter().enumerate().map(|(i, name)| {
+            let vertex = format!("d{}", i);
+            let producers: Vec<_> = d.edges.iter().filter(|(_, to, _)| *to == vertex).map(|(from, _, _)| from).collect();
+            let consumers: Vec<_> = d.edges.iter().filter(|(from, _, _)| *from == vertex).map(|(_, to, _)| to).collect();
+            json!({
+                "id": vertex,
+                "name": name,
+                "producers": producers,
+                "consumers": consumers
+            })
+        }).collect();
+
+        json!({
+            "nodes": nodes,
+            "datasets": datasets
+        })
+    }
+}
+
+/// Places every vertex in a column after everything it depends on; nodes
+/// and datasets alternate, so the pipeline reads from left to right.
+fn layout(d: &Diagram) -> HashMap<String, (usize, usize)> {
+    // nodes come in topological order, so producers are placed before consumers
+    let mut layer: HashMap<String, usize> = HashMap::new();
+    for i in 0..d.nodes.len() {
+        let vertex = format!("n{}", i);
+        let node_layer = d.edges.iter()
+            .filter(|(_, to, _)| *to == vertex)
+            .map(|(from, _, _)| layer.get(from).copied().unwrap_or(0) + 1)
+            .max()
+            .unwrap_or(1);
+        for (_, to, _) in d.edges.iter().filter(|(from, _, _)| *from == vertex) {
+            let l = layer.entry(to.clone()).or_insert(0);
+            *l = (*l).max(node_layer + 1);
+        }
+        layer.insert(vertex, node_layer);
+    }
+
+    let mut vertices: Vec<String> = (0..d.datasets.len()).map(|i| format!("d{}", i)).collect();
+    vertices.extend((0..d.nodes.len()).map(|i| format!("n{}", i)));
+
+    let mut rows: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut r = HashMap::new();
+    for v in vertices {
+        let column = layer.get(&v).copied().unwrap_or(0);
+        let row = rows.entry(column).or_insert(0);
+        r.insert(v, (MARGIN + column * COLUMN_WIDTH, MARGIN + *row * ROW_HEIGHT));
+        *row += 1;
+    }
+    r
+}
+
+fn svg(d: &Diagram, positions: &HashMap<String, (usize, usize)>) -> String {
+    let width = positions.values().map(|(x, _)| x + BOX_WIDTH + MARGIN).max().unwrap_or(MARGIN);
+    let height = positions.values().map(|(_, y)| y + BOX_HEIGHT + MARGIN).max().unwrap_or(MARGIN);
+    let mut r = String::new();
+    writeln!(r, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">", width, height).unwrap();
+
+    for (from, to, local) in &d.edges {
+        let (x1, y1) = positions[from];
+        let (x2, y2) = positions[to];
+        let (x1, y1, x2, y2) = (x1 + BOX_WIDTH, y1 + BOX_HEIGHT / 2, x2, y2 + BOX_HEIGHT / 2);
+        let mid = (x1 + x2) / 2;
+        writeln!(r, "<path class=\"edge\" data-from=\"{}\" data-to=\"{}\" d=\"M{} {} C{} {} {} {} {} {}\"><title>{}</title></path>",
+            from, to, x1, y1, mid, y1, mid, y2, x2, y2, escape(local)).unwrap();
+    }
+    for (i, name) in d.datasets.iter().enumerate() {
+        let (x, y) = positions[&format!("d{}", i)];
+        writeln!(r, "<g class=\"vertex dataset\" data-id=\"d{}\"><ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>",
+            i, x + BOX_WIDTH / 2, y + BOX_HEIGHT / 2, BOX_WIDTH / 2, BOX_HEIGHT / 2, x + BOX_WIDTH / 2, y + BOX_HEIGHT / 2, escape(name)).unwrap();
+    }
+    for (i, n) in d.nodes.iter().enumerate() {
+        let (x, y) = positions[&format!("n{}", i)];
+        let fill = d.color(n).unwrap_or("#ffffff");
+        writeln!(r, "<g class=\"vertex node\" data-id=\"n{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>",
+            i, x, y, BOX_WIDTH, BOX_HEIGHT, fill, x + BOX_WIDTH / 2, y + BOX_HEIGHT / 2, escape(&n.name)).unwrap();
+    }
+
+    r.push_str("</svg>");
+    r
+}
+
+/// Replaces the `{{KEY}}` placeholders of `template` in a single pass, so
+/// placeholders in the values themselves are left alone.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut r = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        r.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let key = rest[2..].find("}}").map(|end| &rest[2..end + 2]);
+        match key.and_then(|k| values.iter().find(|(name, _)| *name == k)) {
+            Some((key, value)) => {
+                r.push_str(value);
+                rest = &rest[key.len() + 4..];
+            },
+            None => {
+                r.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    r.push_str(rest);
+    r
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+body { margin: 0; display: flex; height: 100vh; font: 13px sans-serif; color: #222; }
+#sidebar { width: 280px; overflow: auto; padding: 12px; border-right: 1px solid #ddd; background: #fafafa; }
+#graph { flex: 1; overflow: auto; }
+h1 { font-size: 16px; margin: 0 0 12px; }
+h2 { font-size: 13px; margin: 16px 0 4px; text-transform: uppercase; color: #666; }
+ul { list-style: none; margin: 0; padding: 0; }
+li { padding: 2px 0; }
+a { color: #2a6bb5; cursor: pointer; text-decoration: none; }
+a:hover { text-decoration: underline; }
+.edge { fill: none; stroke: #999; stroke-width: 1.2; }
+.vertex { cursor: pointer; }
+.vertex text { text-anchor: middle; dominant-baseline: middle; font-size: 12px; pointer-events: none; }
+.node rect, .dataset ellipse { stroke: #555; }
+.dataset ellipse { fill: #eef1f5; }
+.dim { opacity: 0.2; }
+.selected rect, .selected ellipse { stroke: #d62728; stroke-width: 3; }
+</style>
+</head>
+<body>
+<div id="sidebar">
+<h1>{{TITLE}}</h1>
+<div id="details"><p>Click a node or dataset.</p></div>
+<h2>Namespaces</h2><ul id="namespaces"></ul>
+<h2>Tags</h2><ul id="tags"></ul>
+<h2>Nodes</h2><ul id="nodes"></ul>
+</div>
+<div id="graph">
+{{SVG}}
+</div>
+<script>
+const data = {{DATA}};
+const byId = {};
+data.nodes.forEach(n => byId[n.id] = n);
+data.datasets.forEach(d => byId[d.id] = d);
+
+function esc(s) {
+  return String(s).replace(/[&<>"]/g, c => ({"&": "&amp;", "<": "&lt;", ">": "&gt;", "\"": "&quot;"}[c]));
+}
+function link(id) {
+  return `<a onclick="select('${id}')">${esc(byId[id].name)}</a>`;
+}
+function highlight(ids) {
+  document.querySelectorAll(".vertex").forEach(el => {
+    el.classList.toggle("dim", ids !== null && !ids.has(el.dataset.id));
+  });
+  document.querySelectorAll(".edge").forEach(el => {
+    el.classList.toggle("dim", ids !== null && !(ids.has(el.dataset.from) && ids.has(el.dataset.to)));
+  });
+}
+function withNeighbours(nodes) {
+  const ids = new Set(nodes.map(n => n.id));
+  nodes.forEach(n => n.inputs.concat(n.outputs).forEach(s => ids.add(s.dataset)));
+  return ids;
+}
+function select(id) {
+  document.querySelectorAll(".vertex").forEach(el => el.classList.toggle("selected", el.dataset.id === id));
+  if (id === null) {
+    highlight(null);
+    document.getElementById("details").innerHTML = "<p>Click a node or dataset.</p>";
+    return;
+  }
+  const v = byId[id];
+  let html = `<h2>${id.startsWith("n") ? "Node" : "Dataset"}</h2><b>${esc(v.name)}</b>`;
+  if (id.startsWith("n")) {
+    const run = v.run || {output_sizes: {}};
+    const size = s => run.output_sizes[byId[s.dataset].name];
+    const source = s => `<li>${link(s.dataset)}${s.local !== byId[s.dataset].name ? ` as ${esc(s.local)}` : ""}${size(s) !== undefined ? ` (${size(s)})` : ""}</li>`;
+    html += `<p>Namespace: ${v.namespace ? esc(v.namespace) : "-"}<br>Tags: ${v.tags.map(esc).join(", ") || "-"}</p>`;
+    if (v.run) {
+      html += `<p>Duration: ${v.run.duration_ms.toFixed(1)} ms</p>`;
+    }
+    html += `<h2>Inputs</h2><ul>${v.inputs.map(source).join("")}</ul>`;
+    html += `<h2>Outputs</h2><ul>${v.outputs.map(source).join("")}</ul>`;
+    highlight(withNeighbours([v]));
+  } else {
+    html += `<h2>Produced by</h2><ul>${v.producers.map(p => `<li>${link(p)}</li>`).join("") || "<li>-</li>"}</ul>`;
+    html += `<h2>Consumed by</h2><ul>${v.consumers.map(c => `<li>${link(c)}</li>`).join("") || "<li>-</li>"}</ul>`;
+    highlight(new Set([id, ...v.producers, ...v.consumers]));
+  }
+  html += `<p><a onclick="select(null)">clear</a></p>`;
+  document.getElementById("details").innerHTML = html;
+}
+function group(listId, keys, nodesOf) {
+  document.getElementById(listId).innerHTML = keys.map(k => `<li><a data-key="${esc(k)}">${esc(k)}</a> (${nodesOf(k).length})</li>`).join("") || "<li>-</li>";
+  document.querySelectorAll(`#${listId} a`).forEach(a => a.onclick = () => highlight(withNeighbours(nodesOf(a.dataset.key))));
+}
+const namespaces = [...new Set(data.nodes.filter(n => n.namespace).map(n => n.namespace))].sort();
+group("namespaces", namespaces, ns => data.nodes.filter(n => n.namespace === ns || (n.namespace || "").startsWith(ns + ".")));
+const tags = [...new Set(data.nodes.flatMap(n => n.tags))].sort();
+group("tags", tags, t => data.nodes.filter(n => n.tags.includes(t)));
+document.getElementById("nodes").innerHTML = data.nodes.map(n => `<li>${link(n.id)}${n.run ? ` <small>${n.run.duration_ms.toFixed(1)} ms</small>` : ""}</li>`).join("");
+document.querySelectorAll(".vertex").forEach(el => el.onclick = () => select(el.dataset.id));
+</script>
+</body>
+</html>
+"##;
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_html_report() -> QupidoResult {
+    use crate::{id, node::Node, container::Container};
+
+    let clean = Node::new([id("raw")], [id("clean")], |_| Ok(Container::new())).name("cleaning").tag("ingest");
+    let report = Node::new([id("clean")], [id("report")], |_| Ok(Container::new())).name("<render>");
+    let pipeline = Pipeline::from_nodes(&[clean, report])?.with_namespace("eu")?;
+
+    let html = HtmlReport::new(&pipeline)
+        .title("oscars")
+        .with_node_run("eu.cleaning", NodeRun {
+            duration: Duration::from_millis(1500),
+            output_sizes: BTreeMap::from([("eu.clean".to_string(), 42)])
+        })
+        .render();
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>oscars</title>"));
+    assert!(!html.contains("{{"));
+    assert!(html.contains("&lt;render&gt;"));
+    assert!(html.contains("\"duration_ms\":1500.0"));
+    assert!(html.contains("\"output_sizes\":{\"eu.clean\":42}"));
+    assert!(html.contains("\"namespace\":\"eu\""));
+
+    // placeholders in user text are not filled in
+    let html = HtmlReport::new(&pipeline).title("{{SVG}} {{DATA}}").render();
+    assert!(html.contains("<title>{{SVG}} {{DATA}}</title>"));
+    assert_eq!(html.matches("<svg").count(), 1);
+
+    // raw, cleaning, clean, render, report from left to right
+    let d = Diagram::new(&pipeline);
+    let positions = layout(&d);
+    let column = |name: &str| {
+        let vertex = match d.datasets.iter().position(|ds| ds == name) {
+            Some(i) => format!("d{}", i),
+            None => format!("n{}", d.nodes.iter().position(|n| n.name == name).unwrap()),
+        };
+        (positions[&vertex].0 - MARGIN) / COLUMN_WIDTH
+    };
+    let columns: Vec<_> = ["eu.raw", "eu.cleaning", "eu.clean", "eu.<render>", "eu.report"].iter().map(|v| column(v)).collect();
+    assert_eq!(columns, vec![0, 1, 2, 3, 4]);
+
+    Ok(())
+}
@@ -1,13 +1,29 @@
-use std::{collections::HashMap, any::{Any, TypeId}, sync::Arc, ops::Add};
+use std::error::Error;
+use std::fmt;
+
+use crate::container::DataType;
 
-use petgraph::{Graph, algo::{toposort}, visit::{NodeIndexable}};
-use uuid::Uuid;
-use std::fmt::Debug;
 
 pub mod node;
 pub mod pipeline;
+pub mod modular;
 pub mod source;
 pub mod container;
+pub mod runner;
+pub mod hooks;
+pub mod report;
+pub mod cache;
+pub mod outputs;
+pub mod resume;
+pub mod catalog;
+pub mod config;
+pub mod parameters;
+pub mod validation;
+pub mod diagram;
+pub mod html;
+pub mod registry;
+#[cfg(feature = "cli")]
+pub mod cli;
 
 #[derive(Clone, Debug)]
 pub enum Tag {
@@ -23,42 +39,196 @@ pub fn tag(tag: impl Into<String>) -> Tag {
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum Source {
-    Id(String)
+    Id(String),
+    /// A value from the parameters file, addressed by its dotted key.
+    /// Its id is the key prefixed with `params:`.
+    Param(String)
 }
 
+pub const PARAMS_PREFIX: &str = "params:";
+
 impl Source {
     pub fn get_id(&self) -> String {
         match self {
             Source::Id(id) => id.clone(),
+            Source::Param(key) => format!("{}{}", PARAMS_PREFIX, key),
+        }
+    }
+
+    pub fn is_param(&self) -> bool {
+        matches!(self, Source::Param(_))
+    }
+
+    /// Prefixes the source with `namespace`. Parameters keep their
+    /// `params:` prefix in front, e.g. `params:ns.key`.
+    pub fn with_namespace(&self, namespace: &str) -> Source {
+        match self {
+            Source::Id(id) => Source::Id(format!("{}.{}", namespace, id)),
+            Source::Param(key) => Source::Param(format!("{}.{}", namespace, key)),
         }
     }
 }
 
+/// Creates a source from its id. Ids starting with `params:` refer to parameters.
 pub fn id(s: impl Into<String>) -> Source {
-    Source::Id(s.into())
+    let s = s.into();
+    match s.strip_prefix(PARAMS_PREFIX) {
+        Some(key) => Source::Param(key.to_string()),
+        None => Source::Id(s)
+    }
+}
+
+pub fn param(key: impl Into<String>) -> Source {
+    Source::Param(key.into())
 }
 
 
 
 
-pub struct Context<T> {
-    pub inputs: crate::container::Container<T>
+pub struct Context {
+    pub inputs: crate::container::Container
 }
 
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum QupidoError {
     DataNotFound(String),
-    InvalidPipeline,
+    InvalidPipeline(Box<validation::Validation>),
     DuplicateData(String),
     DataTypeMismatch {
         id: String,
-        requested: TypeId,
-        stored: TypeId
+        requested: DataType,
+        stored: DataType
+    },
+    /// Every `DataTypeMismatch` [`pipeline::Pipeline::validate`] found.
+    TypeMismatches(Vec<QupidoError>),
+    NodeNotFound(String),
+    /// Overrides of [`modular::Overrides`] naming sources the pipeline
+    /// doesn't have.
+    InvalidOverride(String),
+    DuplicateNode(String),
+    PipelineNotFound(String),
+    DuplicatePipeline(String),
+    RunNotFound(String),
+    AsyncNodeInSyncRun(String),
+    /// The catalog dataset of a source the pipeline uses
+    /// [needs an async runtime](catalog::Dataset::needs_runtime).
+    AsyncDatasetInSyncRun(String),
+    /// A node returning [`outputs::Outputs`] declares outputs that don't
+    /// match them, by local id.
+    OutputMismatch {
+        node: String,
+        returns: outputs::OutputShape,
+        declared: Vec<String>
     },
-    NodeNotFound,
+    DatasetError(String),
+    ConfigError(String),
+    ParameterNotFound(String),
+    /// A node failed while running. `source` is what the node, or loading
+    /// and saving its data, returned.
+    NodeFailed {
+        node: String,
+        namespace: Option<String>,
+        inputs: Vec<String>,
+        source: Box<dyn Error + Send + Sync>
+    },
+    /// An error from outside qupido, e.g. raised by user code inside a node.
+    External(Box<dyn Error + Send + Sync>),
 }
 
-pub type QupidoResult<T = ()> = Result<T, QupidoError>;
+impl QupidoError {
+    /// Wraps any error, or a plain message, so it can be returned from a node.
+    pub fn external(e: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        QupidoError::External(e.into())
+    }
+}
 
+impl fmt::Display for QupidoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QupidoError::DataNotFound(id) => write!(f, "data '{}' not found", id),
+            QupidoError::InvalidPipeline(v) => write!(f, "invalid pipeline:\n{}", v),
+            QupidoError::DuplicateData(id) => write!(f, "data '{}' already exists", id),
+            QupidoError::DataTypeMismatch { id, requested, stored } => {
+                write!(f, "data '{}' was requested as {} but holds {}", id, requested.name, stored.name)
+            },
+            QupidoError::TypeMismatches(errors) => {
+                write!(f, "type mismatches:")?;
+                errors.iter().try_for_each(|e| write!(f, "\n  {}", e))
+            },
+            QupidoError::NodeNotFound(name) => write!(f, "node '{}' not found", name),
+            QupidoError::InvalidOverride(msg) => write!(f, "invalid override: {}", msg),
+            QupidoError::DuplicateNode(name) => write!(f, "node name '{}' is used more than once", name),
+            QupidoError::PipelineNotFound(name) => write!(f, "pipeline '{}' is not registered", name),
+            QupidoError::DuplicatePipeline(name) => write!(f, "pipeline '{}' is already registered", name),
+            QupidoError::RunNotFound(run_id) => write!(f, "no report of run {} found", run_id),
+            QupidoError::AsyncNodeInSyncRun(name) => write!(f, "node '{}' is async and needs an async run", name),
+            QupidoError::AsyncDatasetInSyncRun(id) => write!(f, "the dataset of '{}' needs an async runtime and an async run", id),
+            QupidoError::OutputMismatch { node, returns, declared } => {
+                write!(f, "node '{}' returns {} but declares the outputs [{}]", node, returns, declared.join(", "))
+            },
+            QupidoError::DatasetError(msg) => write!(f, "dataset error: {}", msg),
+            QupidoError::ConfigError(msg) => write!(f, "config error: {}", msg),
+            QupidoError::ParameterNotFound(key) => write!(f, "parameter '{}' not found", key),
+            QupidoError::NodeFailed { node, namespace, inputs, source } => {
+                write!(f, "node '{}'", node)?;
+                if let Some(ns) = namespace {
+                    write!(f, " in namespace '{}'", ns)?;
+                }
+                write!(f, " with inputs [{}] failed: {}", inputs.join(", "), source)
+            },
+            QupidoError::External(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for QupidoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            QupidoError::NodeFailed { source, .. } => Some(source.as_ref()),
+            QupidoError::External(e) => Some(e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for QupidoError {
+    fn from(e: std::io::Error) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for QupidoError {
+    fn from(e: serde_json::Error) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+impl From<serde_yaml::Error> for QupidoError {
+    fn from(e: serde_yaml::Error) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+impl From<toml::de::Error> for QupidoError {
+    fn from(e: toml::de::Error) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+#[cfg(feature = "datafusion")]
+impl From<datafusion::error::DataFusionError> for QupidoError {
+    fn from(e: datafusion::error::DataFusionError) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+#[cfg(feature = "datafusion")]
+impl From<datafusion::arrow::error::ArrowError> for QupidoError {
+    fn from(e: datafusion::arrow::error::ArrowError) -> Self {
+        QupidoError::External(Box::new(e))
+    }
+}
+
+pub type QupidoResult<T = ()> = Result<T, QupidoError>;
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::{id, node::Node, param, pipeline::Pipeline, Source, QupidoResult, QupidoError, PARAMS_PREFIX};
+
+/// Sources of a pipeline to rename instead of namespacing them when the
+/// pipeline is reused, see [`Pipeline::with_overrides`].
+///
+/// Mapping a source to itself keeps it un-namespaced, e.g. to let every
+/// instance of a pipeline read the same raw table.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    inputs: HashMap<Source, Source>,
+    outputs: HashMap<Source, Source>,
+    parameters: HashMap<Source, Source>
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the pipeline input `from` from `to`.
+    pub fn with_input(mut self, from: &str, to: &str) -> Self {
+        self.inputs.insert(id(from), id(to));
+        self
+    }
+
+    /// Writes the output `from` of the pipeline to `to`.
+    pub fn with_output(mut self, from: &str, to: &str) -> Self {
+        self.outputs.insert(id(from), id(to));
+        self
+    }
+
+    /// Takes the parameter `from` from `to`. Keys may leave out the
+    /// `params:` prefix.
+    pub fn with_parameter(mut self, from: &str, to: &str) -> Self {
+        self.parameters.insert(parameter(from), parameter(to));
+        self
+    }
+
+    /// Fails with `InvalidOverride` for sources `pipeline` doesn't have in
+    /// the role they are overridden in.
+    fn check(&self, pipeline: &Pipeline) -> QupidoResult {
+        let inputs = pipeline.inputs();
+        let outputs = pipeline.all_outputs();
+        let parameters = pipeline.parameters();
+
+        let mut invalid: Vec<_> = self.inputs.keys()
+            .filter(|s| !inputs.contains(s))
+            .map(|s| match outputs.contains(s) {
+                true => format!("'{}' is produced by the pipeline, override it as an output", s.get_id()),
+                false => format!("'{}' is not an input of the pipeline", s.get_id()),
+            })
+            .chain(self.outputs.keys().filter(|s| !outputs.contains(s)).map(|s| format!("'{}' is not an output of the pipeline", s.get_id())))
+            .chain(self.parameters.keys().filter(|s| !parameters.contains(s)).map(|s| format!("'{}' is not a parameter of the pipeline", s.get_id())))
+            .collect();
+        invalid.sort();
+
+        match invalid.is_empty() {
+            true => Ok(()),
+            false => Err(QupidoError::InvalidOverride(invalid.join(", "))),
+        }
+    }
+
+    fn get(&self, source: &Source) -> Option<&Source> {
+        self.inputs.get(source)
+            .or_else(|| self.outputs.get(source))
+            .or_else(|| self.parameters.get(source))
+    }
+}
+
+fn parameter(key: &str) -> Source {
+    match key.starts_with(PARAMS_PREFIX) {
+        true => id(key),
+        false => param(key),
+    }
+}
+
+impl Pipeline {
+    /// Reuses the pipeline under `namespace`, like [`Pipeline::with_namespace`],
+    /// except for the sources in `overrides`, which are renamed to what they
+    /// are mapped to instead. Without a namespace, only the overridden sources
+    /// change.
+    ///
+    /// Only inputs no node produces can be overridden as inputs, and only
+    /// parameters the nodes use as parameters.
+    pub fn with_overrides(&self, namespace: Option<&str>, overrides: &Overrides) -> QupidoResult<Pipeline> {
+        overrides.check(self)?;
+
+        let mapping = |node_id: &Source| match (overrides.get(node_id), namespace) {
+            (Some(to), _) => to.clone(),
+            (None, Some(ns)) => node_id.with_namespace(ns),
+            (None, None) => node_id.clone(),
+        };
+        let new_nodes: Vec<_> = self.nodes.iter().map(|n| {
+            let (name, node_namespace) = match namespace {
+                Some(ns) => (
+                    format!("{}.{}", ns, n.name),
+                    Some(n.namespace.as_ref().map(|inner| format!("{}.{}", ns, inner)).unwrap_or(ns.to_string()))
+                ),
+                None => (n.name.clone(), n.namespace.clone()),
+            };
+
+            Node {
+                id: Uuid::new_v4(),
+                inputs: n.inputs.map(mapping),
+                outputs: n.outputs.map(mapping),
+                name,
+                namespace: node_namespace,
+                ..n.clone()
+            }
+        }).collect();
+
+        Self::from_nodes(new_nodes.as_slice())
+    }
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_pipeline_per_region() -> QupidoResult {
+    use std::sync::Arc;
+    use crate::{container::Container, parameters::Parameters, runner::Runner, source::SourceKey};
+
+    const RAW: SourceKey<Vec<i64>> = SourceKey::new("raw");
+    const FEATURES: SourceKey<Vec<i64>> = SourceKey::new("features");
+
+    fn features(raw: &[i64], min: &i64) -> Vec<i64> {
+        raw.iter().cloned().filter(|v| v >= min).collect()
+    }
+
+    fn score(features: &[i64]) -> i64 {
+        features.iter().sum()
+    }
+
+    let pipeline = Pipeline::from_nodes(&[
+        crate::node!(features(RAW, "params:min") -> FEATURES),
+        crate::node!(score(FEATURES) -> "score")
+    ])?;
+
+    let eu = pipeline.with_overrides(Some("eu"), &Overrides::new()
+        .with_input("raw", "raw")
+        .with_output("score", "eu_score"))?;
+    let us = pipeline.with_overrides(Some("us"), &Overrides::new()
+        .with_input("raw", "raw")
+        .with_parameter("min", "params:us_min"))?;
+    let regions = eu.add(&us)?;
+
+    assert_eq!(regions.inputs(), vec![id("raw")]);
+    assert_eq!(regions.parameters(), vec![param("eu.min"), param("us_min")]);
+    assert_eq!(regions.outputs(), vec![id("eu_score"), id("us.score")]);
+    assert_eq!(regions.node("eu.features")?.namespace.as_deref(), Some("eu"));
+
+    let mut container = Container::new();
+    container.insert("raw", vec![1_i64, 5, 10])?;
+    let parameters = Parameters::from_value(serde_json::json!({ "eu": { "min": 5 }, "us_min": 10 }))?;
+    let result = Runner::sequential().with_parameters(Arc::new(parameters)).run(&regions, &container)?;
+    assert_eq!(*result.get::<i64>("eu_score")?, 15);
+    assert_eq!(*result.get::<i64>("us.score")?, 10);
+
+    // without a namespace only the overridden sources change
+    let renamed = pipeline.with_overrides(None, &Overrides::new().with_output("score", "total"))?;
+    assert_eq!(renamed.outputs(), vec![id("total")]);
+    assert_eq!(renamed.inputs(), vec![id("raw")]);
+
+    match pipeline.with_overrides(Some("eu"), &Overrides::new().with_input("features", "shared").with_parameter("max", "max")) {
+        Err(QupidoError::InvalidOverride(msg)) => assert_eq!(
+            msg,
+            "'features' is produced by the pipeline, override it as an output, 'params:max' is not a parameter of the pipeline"
+        ),
+        other => panic!("unexpected result: {:?}", other.map(|p| p.nodes().len())),
+    }
+
+    Ok(())
+}
@@ -1,33 +1,73 @@
-use std::{collections::HashMap, any::{Any, TypeId}, sync::Arc, ops::Add};
+use std::{any::Any, collections::HashMap, future::Future, sync::Arc};
 
-use petgraph::{Graph, algo::{toposort}, visit::{NodeIndexable}};
+use futures::future::{BoxFuture, FutureExt};
 use uuid::Uuid;
-use std::fmt::Debug;
 
-use crate::{source::NodeSources, Tag, Context, QupidoResult, container::Container, tag};
+use crate::{outputs::OutputShape, source::{AsSource, NodeSources}, Tag, Context, QupidoResult, QupidoError, container::{Container, ContainerData, DataType}, tag, Source};
 
 
 #[derive(Clone, Debug)]
-pub struct Node<T> {
+pub struct Node {
     pub id: Uuid,
     pub inputs: NodeSources,
     pub outputs: NodeSources,
     pub tags: Vec<Tag>,
-    pub func: NodeFunc<T>,
-    pub namespace: Option<String>
+    pub func: NodeFunc,
+    pub namespace: Option<String>,
+    pub name: String,
+    /// Whether the name was given with [`Node::name`] rather than derived
+    /// from the sources.
+    pub named: bool,
+    pub version: Option<String>,
+    /// What the function returns when it returns [`Outputs`](crate::outputs::Outputs)
+    /// rather than a container.
+    pub returns: Option<OutputShape>,
+    /// Declared types of inputs and outputs, by the ids the function uses.
+    pub input_types: HashMap<String, DataType>,
+    pub output_types: HashMap<String, DataType>
 }
 
-impl<T> Node<T> where T: Clone {
+impl Node {
     pub fn new<F>(inputs: impl Into<NodeSources>, outputs: impl Into<NodeSources>, func: F) -> Self
-        where F: Fn(&Context<T>) -> QupidoResult<Container<T>> + 'static
+        where F: Fn(&Context) -> QupidoResult<Container> + Send + Sync + 'static
     {
+        let (inputs, outputs) = (inputs.into(), outputs.into());
         Node {
             id: Uuid::new_v4(),
-            inputs: inputs.into(),
-            outputs: outputs.into(),
+            name: default_name(&inputs, &outputs),
+            named: false,
+            inputs,
+            outputs,
             tags: vec![],
-            func: NodeFunc { f: Arc::new(Box::new(func)) },
-            namespace: None
+            func: NodeFunc::Sync(Arc::new(Box::new(func))),
+            namespace: None,
+            version: None,
+            returns: None,
+            input_types: HashMap::new(),
+            output_types: HashMap::new()
+        }
+    }
+
+    /// Creates a node whose function returns a future. The context is handed
+    /// over by value so the future can own it across `.await` points.
+    pub fn new_async<F, Fut>(inputs: impl Into<NodeSources>, outputs: impl Into<NodeSources>, func: F) -> Self
+        where F: Fn(Context) -> Fut + Send + Sync + 'static,
+              Fut: Future<Output = QupidoResult<Container>> + Send + 'static
+    {
+        let (inputs, outputs) = (inputs.into(), outputs.into());
+        Node {
+            id: Uuid::new_v4(),
+            name: default_name(&inputs, &outputs),
+            named: false,
+            inputs,
+            outputs,
+            tags: vec![],
+            func: NodeFunc::Async(Arc::new(Box::new(move |ctx| func(ctx).boxed()))),
+            namespace: None,
+            version: None,
+            returns: None,
+            input_types: HashMap::new(),
+            output_types: HashMap::new()
         }
     }
 
@@ -36,15 +76,371 @@ impl<T> Node<T> where T: Clone {
         s.tags.push(tag(simple_tag));
         s
     }
+
+    /// Names the node. Names must be unique within a pipeline; without one,
+    /// the node is named after its sources, e.g. `[a,b] -> [sum]`, and
+    /// [`Pipeline::from_nodes`](crate::pipeline::Pipeline::from_nodes) tells
+    /// nodes with the same sources apart by their ids.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self.named = true;
+        self
+    }
+
+    /// Versions the node's code for the [`crate::cache::NodeCache`]: cached
+    /// outputs are only reused by a node of the same version.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Declares the type of the input the function calls `id`, for
+    /// [`Pipeline::validate`](crate::pipeline::Pipeline::validate) and for
+    /// checking the value before the function is called.
+    pub fn input_type<T>(mut self, id: &str) -> Self
+        where T: Any
+    {
+        self.input_types.insert(id.to_string(), DataType::of::<T>());
+        self
+    }
+
+    /// Declares the type of the output the function calls `id`; a value of
+    /// another type fails the node as soon as it returns.
+    pub fn output_type<T>(mut self, id: &str) -> Self
+        where T: Any
+    {
+        self.output_types.insert(id.to_string(), DataType::of::<T>());
+        self
+    }
+
+    pub fn has_tag(&self, name: &str) -> bool {
+        self.tags.iter().any(|t| match t {
+            Tag::Tag(t) => t == name,
+        })
+    }
+}
+
+impl Node {
+    /// Builds the context for this node out of its declared inputs in the
+    /// current run state, remapping mapped inputs to the names the node
+    /// function expects.
+    pub(crate) fn context(&self, state: &Container) -> QupidoResult<Context> {
+        let mut c = Container::new();
+        for (node_id, global_id) in self.inputs.pairs() {
+            let v = state.data.get(&global_id.get_id()).ok_or(QupidoError::DataNotFound(global_id.get_id()))?;
+            check_type(self.input_types.get(&node_id.get_id()), &global_id, v.as_ref())?;
+            c.data.insert(node_id.get_id(), v.clone());
+        }
+
+        Ok(Context {
+            inputs: c
+        })
+    }
+
+    /// Calls a sync node function. Async functions can only be awaited, which
+    /// is what [`crate::runner::Runner::run_async`] does.
+    pub(crate) fn call(&self, ctx: &Context) -> QupidoResult<Container> {
+        match &self.func {
+            NodeFunc::Sync(f) => f(ctx),
+            NodeFunc::Async(_) => Err(QupidoError::AsyncNodeInSyncRun(self.name.clone())),
+        }
+    }
+
+    /// Attaches this node's name, namespace and inputs to an error raised
+    /// while running it.
+    pub(crate) fn failed(&self, e: QupidoError) -> QupidoError {
+        let source = match e {
+            QupidoError::NodeFailed { .. } => return e,
+            QupidoError::External(e) => e,
+            e => Box::new(e),
+        };
+
+        QupidoError::NodeFailed {
+            node: self.name.clone(),
+            namespace: self.namespace.clone(),
+            inputs: self.inputs.inputs().iter().map(|s| s.get_id()).collect(),
+            source
+        }
+    }
+
+    /// Moves the declared outputs of a finished node into the run state.
+    pub(crate) fn store_outputs(&self, mut res: Container, state: &mut Container) -> QupidoResult {
+        match &self.outputs {
+            NodeSources::List(l) => {
+                for o in l {
+                    let val = res.data.remove(&o.get_id()).ok_or(QupidoError::DataNotFound(o.get_id()))?;
+                    check_type(self.output_types.get(&o.get_id()), o, val.as_ref())?;
+                    state.data.insert(o.get_id(), val);
+                }
+            },
+            NodeSources::Map(m) => {
+                for (node_id, global_id) in m {
+                    let val = res.data.remove(&node_id.get_id()).ok_or(QupidoError::DataNotFound(node_id.get_id()))?;
+                    check_type(self.output_types.get(&node_id.get_id()), global_id, val.as_ref())?;
+                    state.data.insert(global_id.get_id(), val);
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+fn check_type(declared: Option<&DataType>, source: &Source, value: &dyn ContainerData) -> QupidoResult {
+    match declared {
+        Some(t) if !value.reads_as(t) => Err(QupidoError::DataTypeMismatch {
+            id: source.get_id(),
+            requested: *t,
+            stored: value.data_type()
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The types of the typed ones among `keys`, for [`node!`](crate::node!).
+#[doc(hidden)]
+pub fn key_types(keys: &[&dyn AsSource]) -> HashMap<String, DataType> {
+    keys.iter()
+        .filter_map(|k| k.data_type().map(|t| (k.as_source().get_id(), t)))
+        .collect()
+}
+
+fn default_name(inputs: &NodeSources, outputs: &NodeSources) -> String {
+    fn ids(sources: &NodeSources) -> String {
+        let mut ids: Vec<_> = sources.inputs().iter().map(|s| s.get_id()).collect();
+        if let NodeSources::Map(_) = sources {
+            ids.sort();
+        }
+        ids.join(",")
+    }
+
+    format!("[{}] -> [{}]", ids(inputs), ids(outputs))
 }
 
-#[derive(Clone)]
-pub struct NodeFunc<T> {
-    pub f: Arc<Box<dyn Fn(&Context<T>) -> QupidoResult<Container<T>>>>
+/// Builds a [`Node`] out of a plain function, declaring its inputs and
+/// outputs from the keys it is called with:
+///
+/// ```ignore
+/// fn clean(raw: &DataFrame, threshold: &f64) -> datafusion::error::Result<DataFrame> { ... }
+///
+/// let n = node!(clean(RAW, "params:clean.threshold")? -> CLEAN);
+/// ```
+///
+/// Inputs are handed over by reference, the returned value becomes the
+/// output. Keys are [`SourceKey`](crate::source::SourceKey)s, sources or plain
+/// ids; with typed keys, argument and return types are checked against them
+/// at compile time, with ids they are inferred from the function. A `?` after
+/// the arguments marks functions returning a `Result` whose error converts
+/// into [`QupidoError`], and `async` async functions.
+///
+/// Functions returning several [`Outputs`](crate::outputs::Outputs) list a
+/// key per tuple value, `-> (SUM, PRODUCT)`, or per struct field,
+/// `-> Stats { plus: SUM, times: PRODUCT }`.
+///
+/// The node is named after the function; call `.name()` when the same
+/// function is used more than once in a pipeline.
+#[macro_export]
+macro_rules! node {
+    (async $func:ident ( $($input:expr),* $(,)? ) ? -> $($output:tt)+) => {
+        $crate::node!(@outputs async $func [$($input),*] try $($output)+)
+    };
+    (async $func:ident ( $($input:expr),* $(,)? ) -> $($output:tt)+) => {
+        $crate::node!(@outputs async $func [$($input),*] value $($output)+)
+    };
+    ($func:ident ( $($input:expr),* $(,)? ) ? -> $($output:tt)+) => {
+        $crate::node!(@outputs sync $func [$($input),*] try $($output)+)
+    };
+    ($func:ident ( $($input:expr),* $(,)? ) -> $($output:tt)+) => {
+        $crate::node!(@outputs sync $func [$($input),*] value $($output)+)
+    };
+
+    (@outputs $kind:ident $func:ident [$($input:expr),*] $returns:ident ( $first:expr, $($rest:expr),+ $(,)? )) => {
+        $crate::node!(@build $kind (returning, returning_async) $func [$($input),*] $returns
+            $crate::source::NodeSources::List(::std::vec![
+                $crate::source::AsSource::as_source(&$first), $($crate::source::AsSource::as_source(&$rest)),+
+            ]),
+            ::std::collections::HashMap::new(),
+            |value| {
+                $crate::outputs::keys_of(&value, ($crate::source::AsKey::as_key(&$first), $($crate::source::AsKey::as_key(&$rest)),+));
+                $crate::QupidoResult::Ok(value)
+            }
+        )
+    };
+    (@outputs $kind:ident $func:ident [$($input:expr),*] $returns:ident $ty:ident { $($field:ident : $key:expr),* $(,)? }) => {
+        $crate::node!(@build $kind (returning, returning_async) $func [$($input),*] $returns
+            $crate::source::NodeSources::Map(::std::collections::HashMap::from([
+                $(($crate::id(stringify!($field)), $crate::source::AsSource::as_source(&$key))),*
+            ])),
+            ::std::collections::HashMap::new(),
+            |value| {
+                let value: $ty = value;
+                $($crate::outputs::key_of(&value.$field, &$key);)*
+                $crate::QupidoResult::Ok(value)
+            }
+        )
+    };
+    (@outputs $kind:ident $func:ident [$($input:expr),*] $returns:ident $output:expr) => {
+        $crate::node!(@build $kind (new, new_async) $func [$($input),*] $returns
+            $crate::source::NodeSources::List(::std::vec![$crate::source::AsSource::as_source(&$output)]),
+            $crate::node::key_types(&[&$output]),
+            |value| {
+                let mut outputs = $crate::container::Container::new();
+                outputs.insert_key(&$crate::source::AsKey::as_key(&$output), value)?;
+                $crate::QupidoResult::Ok(outputs)
+            }
+        )
+    };
+
+    (@build sync ($new:ident, $new_async:ident) $func:ident [$($input:expr),*] $returns:ident $outputs:expr, $output_types:expr, |$value:ident| $convert:block) => {{
+        let input_types = $crate::node::key_types(&[$(&$input),*]);
+        let output_types = $output_types;
+        let mut node = $crate::node::Node::$new(
+            $crate::source::NodeSources::List(::std::vec![$($crate::source::AsSource::as_source(&$input)),*]),
+            $outputs,
+            move |ctx: &$crate::Context| {
+                let _ = &ctx;
+                let $value = $func($(ctx.inputs.get_key(&$crate::source::AsKey::as_key(&$input))?),*);
+                let $value = $crate::node!(@returns $returns $value);
+                $convert
+            }
+        ).name(stringify!($func));
+        node.input_types.extend(input_types);
+        node.output_types.extend(output_types);
+        node
+    }};
+    (@build async ($new:ident, $new_async:ident) $func:ident [$($input:expr),*] $returns:ident $outputs:expr, $output_types:expr, |$value:ident| $convert:block) => {{
+        let input_types = $crate::node::key_types(&[$(&$input),*]);
+        let output_types = $output_types;
+        let mut node = $crate::node::Node::$new_async(
+            $crate::source::NodeSources::List(::std::vec![$($crate::source::AsSource::as_source(&$input)),*]),
+            $outputs,
+            move |ctx: $crate::Context| async move {
+                let _ = &ctx;
+                let $value = $func($(ctx.inputs.get_key(&$crate::source::AsKey::as_key(&$input))?),*).await;
+                let $value = $crate::node!(@returns $returns $value);
+                $convert
+            }
+        ).name(stringify!($func));
+        node.input_types.extend(input_types);
+        node.output_types.extend(output_types);
+        node
+    }};
+    (@returns value $value:ident) => {
+        $value
+    };
+    (@returns try $value:ident) => {
+        $value?
+    };
+}
+
+// Node functions are thread safe so nodes, and the pipelines holding them, are
+// `Send + Sync` and can run on any worker.
+pub type NodeFuture = BoxFuture<'static, QupidoResult<Container>>;
+pub type SyncNodeFn = dyn Fn(&Context) -> QupidoResult<Container> + Send + Sync;
+pub type AsyncNodeFn = dyn Fn(Context) -> NodeFuture + Send + Sync;
+
+pub enum NodeFunc {
+    Sync(Arc<Box<SyncNodeFn>>),
+    Async(Arc<Box<AsyncNodeFn>>)
+}
+
+impl NodeFunc {
+    pub fn is_async(&self) -> bool {
+        matches!(self, NodeFunc::Async(_))
+    }
 }
 
-impl<T> std::fmt::Debug for NodeFunc<T> {
+impl Clone for NodeFunc {
+    fn clone(&self) -> Self {
+        match self {
+            NodeFunc::Sync(f) => NodeFunc::Sync(f.clone()),
+            NodeFunc::Async(f) => NodeFunc::Async(f.clone()),
+        }
+    }
+}
+
+impl std::fmt::Debug for NodeFunc {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("NodeFunc").field("f", &"some func").finish()
+        match self {
+            NodeFunc::Sync(_) => f.debug_tuple("NodeFunc::Sync").field(&"some func").finish(),
+            NodeFunc::Async(_) => f.debug_tuple("NodeFunc::Async").field(&"some async func").finish(),
+        }
+    }
+}
+
+
+
+
+
+
+
+
+/// A node named `name` writing its first input, an `i64`, plus one to each
+/// of its outputs; for tests about the shape of a pipeline.
+#[cfg(test)]
+pub(crate) fn pass(name: &str, inputs: &[&str], outputs: &[&str]) -> Node {
+    use crate::id;
+
+    let inputs: Vec<_> = inputs.iter().map(|s| id(*s)).collect();
+    let outputs: Vec<_> = outputs.iter().map(|s| id(*s)).collect();
+    let (first, ids) = (inputs[0].get_id(), outputs.iter().map(|s| s.get_id()).collect::<Vec<_>>());
+    Node::new(inputs.as_slice(), outputs.as_slice(), move |ctx| {
+        let v: &i64 = ctx.inputs.get(&first)?;
+        let mut r = Container::new();
+        ids.iter().try_for_each(|id| r.insert(id, v + 1))?;
+        Ok(r)
+    }).name(name)
+}
+
+#[test]
+fn test_node_macro() -> QupidoResult {
+    use crate::{id, parameters::Parameters, pipeline::Pipeline, runner::Runner, source::SourceKey};
+
+    const PRICES: SourceKey<Vec<f64>> = SourceKey::new("prices");
+    const DISCOUNT: SourceKey<f64> = SourceKey::new("params:discount");
+    const DISCOUNTED: SourceKey<Vec<f64>> = SourceKey::new("discounted");
+    const TOTAL: SourceKey<f64> = SourceKey::new("total");
+
+    fn discount(prices: &[f64], discount: &f64) -> Result<Vec<f64>, QupidoError> {
+        if *discount > 1.0 {
+            return Err(QupidoError::external("discount above 100%"));
+        }
+        Ok(prices.iter().map(|p| p * (1.0 - discount)).collect())
     }
-}
\ No newline at end of file
+
+    async fn total(prices: &[f64]) -> f64 {
+        prices.iter().sum()
+    }
+
+    fn describe(total: &f64) -> String {
+        format!("{:.2}", total)
+    }
+
+    let discounted = crate::node!(discount(PRICES, DISCOUNT)? -> DISCOUNTED);
+    assert_eq!(discounted.name, "discount");
+    assert_eq!(discounted.inputs.inputs(), vec![id("prices"), id("params:discount")]);
+    assert!(discounted.inputs.inputs()[1].is_param());
+    assert_eq!(discounted.outputs.outputs(), vec![id("discounted")]);
+
+    let pipeline = Pipeline::from_nodes(&[
+        discounted,
+        crate::node!(async total(DISCOUNTED) -> TOTAL),
+        crate::node!(describe(TOTAL) -> id("description"))
+    ])?;
+    let mut container = Container::new();
+    container.insert_key(&PRICES, vec![10.0, 30.0])?;
+
+    let mut parameters = Parameters::new();
+    parameters.set("discount", serde_json::json!(0.25));
+    let runner = Runner::sequential().with_parameters(Arc::new(parameters.clone()));
+    let result = futures::executor::block_on(runner.run_async(&pipeline, &container))?;
+    assert_eq!(*result.get_key(&TOTAL)?, 30.0);
+    assert_eq!(result.get::<String>("description")?, "30.00");
+
+    parameters.set("discount", serde_json::json!(2.0));
+    let runner = Runner::sequential().with_parameters(Arc::new(parameters));
+    let result = futures::executor::block_on(runner.run_async(&pipeline, &container));
+    assert!(matches!(result, Err(QupidoError::NodeFailed { node, .. }) if node == "discount"));
+
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+
+use crate::{container::{Container, ContainerData, DataType}, node::Node, source::{AsKey, NodeSources, SourceKey}, Context, QupidoResult, QupidoError};
+
+/// Several values returned by a node function at once, instead of a
+/// [`Container`] filled by hand. Tuples are matched with the node's outputs
+/// in the order they are declared, so they need the outputs as a list;
+/// mapped outputs have no order and are rejected. Structs declared with
+/// [`outputs!`](crate::outputs!) are matched by field name.
+pub trait Outputs: Send + 'static {
+    /// Typed keys for the values, which [`node!`](crate::node!) checks the
+    /// output keys of tuples against. Structs have none.
+    type Keys;
+
+    fn shape() -> OutputShape;
+
+    /// The types of the values, in the order of [`Outputs::shape`].
+    fn types() -> Vec<DataType>;
+
+    /// Puts the values into a container, tuple values under `ids` in order,
+    /// struct fields under their names.
+    fn into_container(self, ids: &[String]) -> QupidoResult<Container>;
+}
+
+/// What a node built with [`Node::returning`] returns, checked against the
+/// outputs it declares before a run starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputShape {
+    /// A tuple of that many values.
+    Tuple(usize),
+    /// A struct with these fields.
+    Fields(Vec<&'static str>)
+}
+
+impl fmt::Display for OutputShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputShape::Tuple(n) => write!(f, "a tuple of {} values", n),
+            OutputShape::Fields(fields) => write!(f, "the fields [{}]", fields.join(", ")),
+        }
+    }
+}
+
+macro_rules! tuple_outputs {
+    ($n:literal; $($t:ident $i:tt),+) => {
+        impl<$($t),+> Outputs for ($($t,)+) where $($t: ContainerData),+ {
+            type Keys = ($(SourceKey<$t>,)+);
+
+            fn shape() -> OutputShape {
+                OutputShape::Tuple($n)
+            }
+
+            fn types() -> Vec<DataType> {
+                vec![$(DataType::of::<$t>()),+]
+            }
+
+            fn into_container(self, ids: &[String]) -> QupidoResult<Container> {
+                if ids.len() != $n {
+                    return Err(QupidoError::external(format!("{} outputs given for a tuple of {} values", ids.len(), $n)));
+                }
+                let mut c = Container::new();
+                $(c.insert(&ids[$i], self.$i)?;)+
+                Ok(c)
+            }
+        }
+    };
+}
+
+tuple_outputs!(2; A 0, B 1);
+tuple_outputs!(3; A 0, B 1, C 2);
+tuple_outputs!(4; A 0, B 1, C 2, D 3);
+tuple_outputs!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_outputs!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// Declares a struct whose fields are the outputs of a node, for
+/// [`Node::returning`] and [`node!`](crate::node!):
+///
+/// ```ignore
+/// outputs! {
+///     pub struct Stats {
+///         pub plus: u32,
+///         pub times: u32
+///     }
+/// }
+/// ```
+///
+/// The node has to declare an output per field, named like the field, e.g.
+/// `[(id("plus"), id("a_plus_b")), (id("times"), id("a_times_b"))]`.
+#[macro_export]
+macro_rules! outputs {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty),*
+        }
+
+        impl $crate::outputs::Outputs for $name {
+            type Keys = ();
+
+            fn shape() -> $crate::outputs::OutputShape {
+                $crate::outputs::OutputShape::Fields(::std::vec![$(stringify!($field)),*])
+            }
+
+            fn types() -> ::std::vec::Vec<$crate::container::DataType> {
+                ::std::vec![$($crate::container::DataType::of::<$ty>()),*]
+            }
+
+            fn into_container(self, _ids: &[::std::string::String]) -> $crate::QupidoResult<$crate::container::Container> {
+                let mut c = $crate::container::Container::new();
+                $(c.insert(stringify!($field), self.$field)?;)*
+                Ok(c)
+            }
+        }
+    };
+}
+
+impl Node {
+    /// Creates a node whose function returns several [`Outputs`] at once.
+    /// Runners check that the declared outputs match what the function
+    /// returns before running anything.
+    pub fn returning<F, O>(inputs: impl Into<NodeSources>, outputs: impl Into<NodeSources>, func: F) -> Self
+        where F: Fn(&Context) -> QupidoResult<O> + Send + Sync + 'static,
+              O: Outputs
+    {
+        let outputs = outputs.into();
+        let ids = local_ids(&outputs);
+        let mut node = Node::new(inputs, outputs, move |ctx| func(ctx)?.into_container(&ids));
+        node.returns = Some(O::shape());
+        node.output_types = output_types::<O>(&node.outputs);
+        node
+    }
+
+    /// Like [`Node::returning`] for a function returning a future.
+    pub fn returning_async<F, Fut, O>(inputs: impl Into<NodeSources>, outputs: impl Into<NodeSources>, func: F) -> Self
+        where F: Fn(Context) -> Fut + Send + Sync + 'static,
+              Fut: Future<Output = QupidoResult<O>> + Send + 'static,
+              O: Outputs
+    {
+        let outputs = outputs.into();
+        let ids = local_ids(&outputs);
+        let func = std::sync::Arc::new(func);
+        let mut node = Node::new_async(inputs, outputs, move |ctx| {
+            let (func, ids) = (func.clone(), ids.clone());
+            async move { func(ctx).await?.into_container(&ids) }
+        });
+        node.returns = Some(O::shape());
+        node.output_types = output_types::<O>(&node.outputs);
+        node
+    }
+
+    /// Fails with `OutputMismatch` when the declared outputs don't fit what
+    /// the node function returns, or a tuple is returned into mapped outputs.
+    pub(crate) fn check_outputs(&self) -> QupidoResult {
+        let Some(returns) = &self.returns else {
+            return Ok(());
+        };
+        let declared = local_ids(&self.outputs);
+        let fits = match returns {
+            OutputShape::Tuple(n) => matches!(self.outputs, NodeSources::List(_)) && declared.len() == *n,
+            OutputShape::Fields(fields) => {
+                let mut fields: Vec<_> = fields.iter().map(|f| f.to_string()).collect();
+                let mut declared = declared.clone();
+                fields.sort();
+                declared.sort();
+                fields == declared
+            }
+        };
+        if fits {
+            return Ok(());
+        }
+        Err(QupidoError::OutputMismatch {
+            node: self.name.clone(),
+            returns: returns.clone(),
+            declared
+        })
+    }
+}
+
+/// The ids the node function uses for `outputs`, lists in declared order.
+fn local_ids(outputs: &NodeSources) -> Vec<String> {
+    outputs.pairs().into_iter().map(|(local, _)| local.get_id()).collect()
+}
+
+fn output_types<O: Outputs>(outputs: &NodeSources) -> HashMap<String, DataType> {
+    let ids = match O::shape() {
+        OutputShape::Tuple(_) => local_ids(outputs),
+        OutputShape::Fields(fields) => fields.iter().map(|f| f.to_string()).collect(),
+    };
+    ids.into_iter().zip(O::types()).collect()
+}
+
+/// Used by [`node!`](crate::node!) to check output keys against the type of
+/// the value returned, does nothing at runtime.
+#[doc(hidden)]
+pub fn keys_of<O: Outputs>(_value: &O, _keys: O::Keys) {}
+
+/// Like [`keys_of`] for a single field of a struct.
+#[doc(hidden)]
+pub fn key_of<U>(_value: &U, _key: &impl AsKey<U>) {}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+outputs! {
+    #[derive(Debug)]
+    struct Stats {
+        mean: f64,
+        max: f64
+    }
+}
+
+#[test]
+fn test_multiple_outputs() -> QupidoResult {
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    use crate::{id, pipeline::Pipeline, runner::Runner};
+
+    const VALUES: SourceKey<Vec<f64>> = SourceKey::new("values");
+    const MEAN: SourceKey<f64> = SourceKey::new("mean");
+    const MAX: SourceKey<f64> = SourceKey::new("max");
+
+    fn stats(values: &[f64]) -> Stats {
+        Stats {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            max: values.iter().cloned().fold(f64::MIN, f64::max)
+        }
+    }
+
+    async fn spread(mean: &f64, max: &f64) -> (f64, String) {
+        (max - mean, format!("{} above the mean", max - mean))
+    }
+
+    let pipeline = Pipeline::from_nodes(&[
+        crate::node!(stats(VALUES) -> Stats { mean: MEAN, max: MAX }),
+        crate::node!(async spread(MEAN, MAX) -> ("spread", "summary"))
+    ])?;
+    let mut container = Container::new();
+    container.insert_key(&VALUES, vec![1.0, 2.0, 6.0])?;
+    let result = futures::executor::block_on(pipeline.run_async(&container))?;
+    assert_eq!(*result.get_key(&MEAN)?, 3.0);
+    assert_eq!(*result.get::<f64>("spread")?, 3.0);
+    assert_eq!(result.get::<String>("summary")?, "3 above the mean");
+
+    // declared outputs not matching the return value fail the run before any node runs
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let first = Node::returning([id("values")], [id("count"), id("total")], move |ctx| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let values: &Vec<f64> = ctx.inputs.get("values")?;
+        Ok((values.len(), values.iter().sum::<f64>()))
+    }).name("first");
+    let wrong = Node::returning([id("total")], [(id("mean"), id("avg")), (id("min"), id("minimum"))], |ctx| {
+        let total: &f64 = ctx.inputs.get("total")?;
+        Ok(Stats { mean: *total, max: *total })
+    }).name("wrong");
+    let pipeline = Pipeline::from_nodes(&[first, wrong])?;
+    for workers in [1, 2] {
+        match Runner::parallel(workers).run(&pipeline, &container) {
+            Err(QupidoError::OutputMismatch { node, returns, declared }) => {
+                assert_eq!(node, "wrong");
+                assert_eq!(returns, OutputShape::Fields(vec!["mean", "max"]));
+                assert_eq!(declared, vec!["mean", "min"]);
+            },
+            other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+        }
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    let tuple = Node::returning([id("values")], [id("count")], |_| Ok((1_usize, 2_usize))).name("tuple");
+    let err = Pipeline::from_nodes(&[tuple])?.run(&container).unwrap_err();
+    assert_eq!(err.to_string(), "node 'tuple' returns a tuple of 2 values but declares the outputs [count]");
+
+    // mapped outputs have no order to match a tuple with
+    let mapped = Node::returning([id("values")], [(id("count"), id("n")), (id("total"), id("sum"))], |_| Ok((1_usize, 2.0_f64))).name("mapped");
+    let err = Pipeline::from_nodes(&[mapped])?.run(&container).unwrap_err();
+    assert_eq!(err.to_string(), "node 'mapped' returns a tuple of 2 values but declares the outputs [count, total]");
+
+    Ok(())
+}
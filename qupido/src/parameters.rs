@@ -0,0 +1,236 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{config::{flatten_into, merge}, container::{Container, ContainerData, DataType}, Source, QupidoResult, QupidoError};
+
+/// Values nodes can depend on through `params:` sources, usually loaded from
+/// `parameters.yml` with [`crate::config::ConfigLoader::parameters`].
+///
+/// Nested keys are addressed with dots: `params:model.learning_rate` is the
+/// `learning_rate` entry of the `model` mapping, and `params:model` is the
+/// whole mapping.
+///
+/// In the container, whole numbers are stored as a [`WholeNumber`], which
+/// reads as both `i64` and `f64`, other numbers as `f64`, and booleans and
+/// strings as `bool` and `String`. Lists and mappings are stored as a
+/// `serde_json::Value`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameters {
+    root: Value
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            root: Value::Object(Map::new())
+        }
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_value(root: Value) -> QupidoResult<Self> {
+        if !root.is_object() {
+            return Err(QupidoError::ConfigError("parameters must be a mapping".to_string()));
+        }
+
+        Ok(Parameters {
+            root
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        key.split('.').try_fold(&self.root, |v, part| v.get(part))
+    }
+
+    pub fn get_as<T>(&self, key: &str) -> QupidoResult<T>
+        where T: DeserializeOwned
+    {
+        let v = self.get(key).ok_or(QupidoError::ParameterNotFound(key.to_string()))?;
+        serde_json::from_value(v.clone()).map_err(|e| QupidoError::ConfigError(format!("parameter '{}': {}", key, e)))
+    }
+
+    /// Sets a single, possibly nested, value; used for overrides.
+    pub fn set(&mut self, key: &str, value: Value) {
+        let mut overlay = value;
+        for part in key.split('.').rev() {
+            let mut m = Map::new();
+            m.insert(part.to_string(), overlay);
+            overlay = Value::Object(m);
+        }
+        merge(&mut self.root, overlay);
+    }
+
+    /// Deep-merges `other` over these parameters.
+    pub fn merge(&mut self, other: &Parameters) {
+        merge(&mut self.root, other.root.clone());
+    }
+
+    /// Dotted keys of all leaf values.
+    pub fn keys(&self) -> Vec<String> {
+        let mut flat = HashMap::new();
+        flatten_into(&self.root, "", &mut flat);
+        let mut r: Vec<_> = flat.into_keys().collect();
+        r.sort();
+        r
+    }
+
+    /// Puts the value of every parameter source into the container, unless
+    /// it is already there.
+    pub fn insert_into(&self, sources: &[Source], container: &mut Container) -> QupidoResult {
+        for source in sources {
+            let Source::Param(key) = source else {
+                continue;
+            };
+            if container.contains(&source.get_id()) {
+                continue;
+            }
+
+            let v = self.get(key).ok_or(QupidoError::ParameterNotFound(key.clone()))?;
+            container.data.insert(source.get_id(), param_value(v));
+        }
+
+        Ok(())
+    }
+}
+
+/// A whole number parameter. `learning_rate: 1` doesn't say whether it is
+/// meant as an integer or a float, so [`Container::get`] returns it as
+/// either `i64` or `f64`, whichever the node asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WholeNumber {
+    pub int: i64,
+    pub float: f64
+}
+
+impl WholeNumber {
+    pub fn new(int: i64) -> Self {
+        WholeNumber {
+            int,
+            float: int as f64
+        }
+    }
+
+    /// The number as `T`, if `T` is `i64` or `f64`.
+    pub fn read<T>(&self) -> Option<&T>
+        where T: Any
+    {
+        (&self.int as &dyn Any).downcast_ref::<T>()
+            .or_else(|| (&self.float as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn reads_as(t: &DataType) -> bool {
+        *t == DataType::of::<i64>() || *t == DataType::of::<f64>()
+    }
+}
+
+fn param_value(v: &Value) -> Arc<dyn ContainerData> {
+    match v {
+        Value::Bool(b) => Arc::new(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Arc::new(WholeNumber::new(i)),
+            None => Arc::new(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => Arc::new(s.clone()),
+        v => Arc::new(v.clone()),
+    }
+}
+
+
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_parameters_in_pipeline() -> QupidoResult {
+    use crate::{id, param, node::Node, pipeline::Pipeline, runner::Runner};
+
+    let train = Node::new([id("features"), param("model.learning_rate"), param("model.epochs")], [id("model")], |ctx| {
+        let features: &Vec<f64> = ctx.inputs.get("features")?;
+        let lr: &f64 = ctx.inputs.get("params:model.learning_rate")?;
+        let epochs: &i64 = ctx.inputs.get("params:model.epochs")?;
+
+        let mut r = Container::new();
+        r.insert("model", features.iter().sum::<f64>() * lr * *epochs as f64)?;
+        Ok(r)
+    });
+    let pipeline = Pipeline::from_nodes(std::slice::from_ref(&train))?;
+
+    assert_eq!(pipeline.inputs(), vec![id("features")]);
+    assert_eq!(pipeline.parameters(), vec![id("params:model.epochs"), id("params:model.learning_rate")]);
+
+    let mut parameters = Parameters::from_value(serde_json::json!({
+        "model": {
+            "learning_rate": 0.5,
+            "epochs": 4
+        }
+    }))?;
+    assert_eq!(parameters.keys(), vec!["model.epochs", "model.learning_rate"]);
+
+    let mut container = Container::new();
+    container.insert("features", vec![1.0, 2.0])?;
+
+    let runner = Runner::sequential().with_parameters(Arc::new(parameters.clone()));
+    let result = runner.run(&pipeline, &container)?;
+    assert_eq!(*result.get::<f64>("model")?, 6.0);
+
+    parameters.set("model.epochs", serde_json::json!(1));
+    assert_eq!(parameters.get_as::<i64>("model.epochs")?, 1);
+    assert_eq!(parameters.get_as::<f64>("model.learning_rate")?, 0.5);
+
+    // whole numbers read as f64 as well, declared or not
+    parameters.set("model.learning_rate", serde_json::json!(1));
+    let runner = Runner::sequential().with_parameters(Arc::new(parameters.clone()));
+    assert_eq!(*runner.run(&pipeline, &container)?.get::<f64>("model")?, 3.0);
+    let typed = Pipeline::from_nodes(&[train.clone().input_type::<f64>("params:model.learning_rate").input_type::<i64>("params:model.epochs")])?;
+    assert_eq!(*runner.run(&typed, &container)?.get::<f64>("model")?, 3.0);
+    let mut state = Container::new();
+    parameters.insert_into(&pipeline.parameters(), &mut state)?;
+    assert_eq!(*state.get::<i64>("params:model.learning_rate")?, 1);
+    assert!(matches!(state.get::<String>("params:model.epochs"), Err(QupidoError::DataTypeMismatch { stored, .. }) if stored == DataType::of::<WholeNumber>()));
+
+    let mut missing = Parameters::new();
+    missing.set("model.learning_rate", serde_json::json!(0.1));
+    match Runner::sequential().with_parameters(Arc::new(missing)).run(&pipeline, &container) {
+        Err(QupidoError::ParameterNotFound(key)) => assert_eq!(key, "model.epochs"),
+        other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+    }
+
+    match pipeline.run(&container) {
+        Err(QupidoError::ParameterNotFound(key)) => assert_eq!(key, "model.epochs"),
+        other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parameters_namespaced() -> QupidoResult {
+    use crate::{id, param, node::Node, pipeline::Pipeline};
+
+    let n = Node::new([id("x"), param("factor")], [id("y")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let factor: &i64 = ctx.inputs.get("params:factor")?;
+        let mut r = Container::new();
+        r.insert("y", x * factor)?;
+        Ok(r)
+    });
+    let pipeline = Pipeline::from_nodes(&[n])?.with_namespace("eu")?;
+    assert_eq!(pipeline.inputs(), vec![id("eu.x")]);
+    assert_eq!(pipeline.parameters(), vec![param("eu.factor")]);
+    assert_eq!(param("eu.factor").get_id(), "params:eu.factor");
+
+    Ok(())
+}
@@ -1,46 +1,67 @@
-use std::{collections::HashMap, ops::Add};
-use std::fmt::Debug;
+use std::collections::{HashMap, HashSet};
+#[cfg(test)]
+use std::{fmt::Debug, ops::Add};
 
-use petgraph::visit::NodeIndexable;
-use petgraph::{Graph, algo::toposort};
+use petgraph::graph::NodeIndex;
+use petgraph::visit::{EdgeRef, NodeIndexable};
+use petgraph::{Graph, Direction, algo::toposort};
 use uuid::Uuid;
 
-use crate::{node::Node, Source, QupidoResult, QupidoError, container::Container, source::NodeSources, Context, id};
-
-#[derive(Debug)]
-pub struct Pipeline<T> {
-    nodes: Vec<Node<T>>,
-    graph: Graph<Uuid, Source>
+use crate::{modular::Overrides, node::Node, source::NodeSources, validation::{self, Validation}, Source, QupidoResult, QupidoError, container::{Container, DataType}, runner::Runner};
+#[cfg(test)]
+use crate::id;
+
+/// Nodes connected through the sources they consume and produce, kept in
+/// topological order.
+///
+/// Node functions have to be `Send + Sync`, so pipelines are too: they can be
+/// moved into async tasks, shared between threads or kept in a static
+/// registry.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) graph: Graph<Uuid, Source>
 }
 
-impl<T> Pipeline<T> where T: Clone {
-    pub fn from_nodes(nodes: &[Node<T>]) -> QupidoResult<Pipeline<T>> {
-        let mut g = Graph::<Uuid, Source>::new();
-        
-        let graph_nodes: HashMap<Uuid, _> = nodes.iter()
-            .map(|n| (n.id, g.add_node(n.id)))
-            .collect();
+fn unique_names(nodes: &[Node]) -> QupidoResult<Vec<Node>> {
+    let mut names = HashSet::new();
+    for n in nodes.iter().filter(|n| n.named) {
+        if !names.insert(n.name.clone()) {
+            return Err(QupidoError::DuplicateNode(n.name.clone()));
+        }
+    }
 
-        let mut outputs = HashMap::new();
-        for n in nodes {
-            for o in &n.outputs.outputs() {
-                if let Some(_) = outputs.insert(o.clone(), n.id) {
-                    return Err(QupidoError::DuplicateData(o.get_id()));
-                }
-            }
+    let mut nodes = nodes.to_vec();
+    for n in nodes.iter_mut().filter(|n| !n.named) {
+        if !names.insert(n.name.clone()) {
+            n.name = format!("{} ({})", n.name, &n.id.simple().to_string()[..8]);
+            names.insert(n.name.clone());
         }
-        for n in nodes {
-            let dst = graph_nodes.get(&n.id).ok_or(QupidoError::NodeNotFound)?;
-            
-            for i in n.inputs.inputs() {
-                if let Some(node_source_id) = outputs.get(&i) {
-                    let src = graph_nodes.get(node_source_id).ok_or(QupidoError::NodeNotFound)?;
-                    g.add_edge(src.clone(), dst.clone(), i.clone());
-                }
-            }
+    }
+    Ok(nodes)
+}
+
+impl Pipeline {
+    /// Assembles nodes into a pipeline. Names given with [`Node::name`] must
+    /// be unique, and the nodes must neither depend on each other in a cycle
+    /// nor produce the same source twice. A single source produced twice is
+    /// a `DuplicateData` error naming it; anything else wrong is an
+    /// `InvalidPipeline` error with the whole [`Validation`]. Unnamed nodes
+    /// whose default names clash get the start of their id appended, e.g.
+    /// `[a] -> [] (67e55044)`.
+    pub fn from_nodes(nodes: &[Node]) -> QupidoResult<Pipeline> {
+        let nodes = &unique_names(nodes)?;
+
+        let g = validation::graph(nodes);
+        let validation = Validation::check(nodes, &g);
+        if let (true, [(source, _)]) = (validation.cycles.is_empty(), validation.overwritten.as_slice()) {
+            return Err(QupidoError::DuplicateData(source.get_id()));
+        }
+        if !validation.is_valid() {
+            return Err(QupidoError::InvalidPipeline(Box::new(validation)));
         }
 
-        let sorted = toposort(&g, None).map_err(|e| QupidoError::InvalidPipeline)?;
+        let sorted = toposort(&g, None).expect("validated pipelines have no cycles");
 
         Ok(Pipeline {
             nodes: sorted.into_iter()
@@ -50,56 +71,81 @@ impl<T> Pipeline<T> where T: Clone {
         })
     }
 
-    pub fn run(&self, container: &Container<T>) -> QupidoResult<Container<T>> {
-
-        let mut container_run_state = container.clone();
-
-        for n in &self.nodes {
-
-            // remap
-            let container_input = {
-                let mut c = container_run_state.clone();
-                match &n.inputs {
-                    NodeSources::List(_) => (),
-                    NodeSources::Map(m) => {
-                        for (node_id, global_id) in m {
-                            let v = c.data.get(&global_id.get_id()).ok_or(QupidoError::DataNotFound(global_id.get_id()))?;
-                            c.data.insert(node_id.get_id(), v.clone());
-                        }
-                    },
+    /// Checks the declared types along every edge: a consumer declaring
+    /// another type for an input than its producer declares for the output
+    /// is a `DataTypeMismatch`, with the consumer's type requested and the
+    /// producer's stored. All mismatches are returned at once, as
+    /// `TypeMismatches`; sources without a declared type on either side are
+    /// not checked.
+    pub fn validate(&self) -> QupidoResult {
+        let nodes: HashMap<_, _> = self.nodes.iter().map(|n| (n.id, n)).collect();
+        let declared = |types: &HashMap<String, DataType>, sources: &NodeSources, global: &Source| {
+            sources.pairs().into_iter()
+                .find(|(_, g)| g == global)
+                .and_then(|(local, _)| types.get(&local.get_id()).copied())
+        };
+
+        let mut mismatches = vec![];
+        for e in self.graph.edge_references() {
+            let (producer, consumer) = (nodes[&self.graph[e.source()]], nodes[&self.graph[e.target()]]);
+            let stored = declared(&producer.output_types, &producer.outputs, e.weight());
+            let requested = declared(&consumer.input_types, &consumer.inputs, e.weight());
+            if let (Some(stored), Some(requested)) = (stored, requested) {
+                if stored != requested {
+                    mismatches.push(QupidoError::DataTypeMismatch {
+                        id: e.weight().get_id(),
+                        requested,
+                        stored
+                    });
                 }
-                c
-            };
-            let ctx = Context {
-                inputs: container_input
-            };
-            let mut res = (n.func.f)(&ctx)?;
-            
-            match &n.outputs {
-                NodeSources::List(l) => {
-                    for o in l {
-                        let val = res.data.remove(&o.get_id()).ok_or(QupidoError::DataNotFound(o.get_id()))?;
-                        container_run_state.data.insert(o.get_id(), val);
-                    }
-                },
-                NodeSources::Map(m) => {
-                    for (node_id, global_id) in m {
-                        let val = res.data.remove(&node_id.get_id()).ok_or(QupidoError::DataNotFound(node_id.get_id()))?;
-                        container_run_state.data.insert(global_id.get_id(), val);
-                    }
-                },
             }
+        }
 
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(QupidoError::TypeMismatches(mismatches))
         }
+    }
+
+    /// Runs the pipeline sequentially, one node after the other.
+    pub fn run(&self, container: &Container) -> QupidoResult<Container> {
+        Runner::sequential().run(self, container)
+    }
+
+    /// Runs the pipeline, executing independent nodes concurrently on
+    /// `workers` threads.
+    pub fn run_parallel(&self, container: &Container, workers: usize) -> QupidoResult<Container> {
+        Runner::parallel(workers).run(self, container)
+    }
 
-        Ok(container_run_state)
+    /// Runs the pipeline on the caller's async runtime, awaiting async nodes.
+    /// Nodes run one at a time; use [`Runner::parallel`] to run independent
+    /// nodes concurrently.
+    pub async fn run_async(&self, container: &Container) -> QupidoResult<Container> {
+        Runner::sequential().run_async(self, container).await
     }
 
+    /// Data inputs no node of the pipeline produces, each listed once even
+    /// if several nodes consume it. Parameters are not among them, they are
+    /// listed by [`Pipeline::parameters`].
     pub fn inputs(&self) -> Vec<Source> {
+        let mut r: Vec<_> = self.free_inputs().into_iter().filter(|s| !s.is_param()).collect();
+        r.dedup();
+        r
+    }
+
+    pub fn parameters(&self) -> Vec<Source> {
+        let mut r: Vec<_> = self.free_inputs().into_iter().filter(|s| s.is_param()).collect();
+        r.dedup();
+        r
+    }
+
+    fn free_inputs(&self) -> Vec<Source> {
         let mut r = vec![];
         
         for n in &self.nodes {
-            let idx = self.graph.node_weights().position(|id| n.id == *id).ok_or(QupidoError::NodeNotFound).unwrap();
+            let idx = self.graph.node_weights().position(|id| n.id == *id).ok_or(QupidoError::NodeNotFound(n.name.clone())).unwrap();
             let idx = self.graph.from_index(idx);
         
             let incoming_edges = self.graph.edges_directed(idx, petgraph::Direction::Incoming).collect::<Vec<_>>();
@@ -119,7 +165,7 @@ impl<T> Pipeline<T> where T: Clone {
         let mut r = vec![];
         
         for n in &self.nodes {
-            let idx = self.graph.node_weights().position(|id| n.id == *id).ok_or(QupidoError::NodeNotFound).unwrap();
+            let idx = self.graph.node_weights().position(|id| n.id == *id).ok_or(QupidoError::NodeNotFound(n.name.clone())).unwrap();
             let idx = self.graph.from_index(idx);
         
             let outgoing_edges = self.graph.edges_directed(idx, petgraph::Direction::Outgoing).collect::<Vec<_>>();
@@ -149,33 +195,105 @@ impl<T> Pipeline<T> where T: Clone {
         r
     }
 
-    pub fn add(&self, other: &Pipeline<T>) -> QupidoResult<Pipeline<T>> {
+    pub fn add(&self, other: &Pipeline) -> QupidoResult<Pipeline> {
         let mut a = self.nodes.clone();
         a.extend_from_slice(&other.nodes);
         Self::from_nodes(a.as_slice())
     }
 
-    pub fn with_namespace(&self, namespace: &str) -> QupidoResult<Pipeline<T>> {
-        let new_nodes: Vec<_> = self.nodes.iter().map(|n| {
-            let mapping = |node_id: &Source| {
-                let global_id = id(format!("{}.{}", namespace, node_id.get_id()));
-                global_id
-            };
-            let new_inputs = n.inputs.map(mapping);
-            let new_outputs = n.outputs.map(mapping);
-            
-            Node {
-                id: Uuid::new_v4(),
-                inputs: new_inputs,
-                outputs: new_outputs,
-                tags: n.tags.clone(),
-                func: n.func.clone(),
-                namespace: Some(n.namespace.as_ref().map(|ns| format!("{}.{}", namespace, ns)).unwrap_or(namespace.to_string()))
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Nodes tagged with at least one of `tags`.
+    pub fn only_nodes_with_tags(&self, tags: &[&str]) -> QupidoResult<Pipeline> {
+        self.filter(|n| tags.iter().any(|t| n.has_tag(t)))
+    }
+
+    /// Nodes consuming any of `inputs`, and everything downstream of them.
+    pub fn from_inputs(&self, inputs: &[Source]) -> QupidoResult<Pipeline> {
+        let mut start = vec![];
+        for i in inputs {
+            let consumers: Vec<_> = self.nodes.iter().filter(|n| n.inputs.inputs().contains(i)).map(|n| n.id).collect();
+            if consumers.is_empty() {
+                return Err(QupidoError::DataNotFound(i.get_id()));
             }
+            start.extend(consumers);
+        }
 
-        }).collect();
+        let keep = self.reachable(&start, Direction::Outgoing);
+        self.filter(|n| keep.contains(&n.id))
+    }
 
-        Self::from_nodes(new_nodes.as_slice())
+    /// Nodes producing any of `outputs`, and everything they depend on.
+    pub fn to_outputs(&self, outputs: &[Source]) -> QupidoResult<Pipeline> {
+        let mut start = vec![];
+        for o in outputs {
+            let producer = self.nodes.iter().find(|n| n.outputs.outputs().contains(o))
+                .ok_or(QupidoError::DataNotFound(o.get_id()))?;
+            start.push(producer.id);
+        }
+
+        let keep = self.reachable(&start, Direction::Incoming);
+        self.filter(|n| keep.contains(&n.id))
+    }
+
+    /// The named nodes and everything downstream of them.
+    pub fn from_nodes_named(&self, names: &[&str]) -> QupidoResult<Pipeline> {
+        let start = self.nodes_named(names)?;
+        let keep = self.reachable(&start, Direction::Outgoing);
+        self.filter(|n| keep.contains(&n.id))
+    }
+
+    /// The named nodes and everything they depend on.
+    pub fn to_nodes(&self, names: &[&str]) -> QupidoResult<Pipeline> {
+        let start = self.nodes_named(names)?;
+        let keep = self.reachable(&start, Direction::Incoming);
+        self.filter(|n| keep.contains(&n.id))
+    }
+
+    /// Looks a node up by name.
+    pub fn node(&self, name: &str) -> QupidoResult<&Node> {
+        self.nodes.iter()
+            .find(|n| n.name == name)
+            .ok_or_else(|| QupidoError::NodeNotFound(name.to_string()))
+    }
+
+    fn nodes_named(&self, names: &[&str]) -> QupidoResult<Vec<Uuid>> {
+        names.iter()
+            .map(|name| self.node(name).map(|n| n.id))
+            .collect()
+    }
+
+    /// Walks the graph from `start` along `direction`, returning the ids of
+    /// all visited nodes including the starting ones.
+    fn reachable(&self, start: &[Uuid], direction: Direction) -> HashSet<Uuid> {
+        let mut r = HashSet::new();
+        let mut stack: Vec<NodeIndex> = self.graph.node_indices()
+            .filter(|idx| start.contains(&self.graph[*idx]))
+            .collect();
+
+        while let Some(idx) = stack.pop() {
+            if !r.insert(self.graph[idx]) {
+                continue;
+            }
+            stack.extend(self.graph.neighbors_directed(idx, direction));
+        }
+
+        r
+    }
+
+    fn filter<F>(&self, keep: F) -> QupidoResult<Pipeline>
+        where F: Fn(&Node) -> bool
+    {
+        let nodes: Vec<_> = self.nodes.iter().filter(|n| keep(n)).cloned().collect();
+        Self::from_nodes(&nodes)
+    }
+
+    /// Prefixes every source, node name and namespace with `namespace`;
+    /// see [`Pipeline::with_overrides`] to keep some sources as they are.
+    pub fn with_namespace(&self, namespace: &str) -> QupidoResult<Pipeline> {
+        self.with_overrides(Some(namespace), &Overrides::default())
     }
 }
 
@@ -209,14 +327,14 @@ fn test_nodes_topo() -> QupidoResult {
         }).tag("math").tag("plus").tag("multiply");
 
     let b = Node::new([id("a_plus_b")], [id("squared")], |ctx| {
-        let v = ctx.inputs.get("a_plus_b")?;
+        let v: &u32 = ctx.inputs.get("a_plus_b")?;
         let mut r = Container::new();
         r.insert("squared", v * v)?;
         Ok(r)
     }).tag("math");
 
     let c = Node::new([id("squared")], [id("squared_plus_1")], |ctx| {
-        let v = ctx.inputs.get("squared")?;
+        let v: &u32 = ctx.inputs.get("squared")?;
         let mut r = Container::new();
         r.insert("squared_plus_1", v + 1)?;
         Ok(r)
@@ -241,14 +359,14 @@ fn test_nodes_topo() -> QupidoResult {
         let result = pipeline.run(&container)?;
         println!("result: {:#?}", result);
 
-        let a_plus_b = result.get("a_plus_b")?;
+        let a_plus_b: &u32 = result.get("a_plus_b")?;
         assert_eq!(*a_plus_b, 8);
     }
 
 
     let pb = Pipeline::from_nodes(&[
         Node::new([id("squared_plus_1")], (), |ctx| {
-            println!("val: {:?}", ctx.inputs.get("squared_plus_1")?);
+            println!("val: {:?}", ctx.inputs.get::<u32>("squared_plus_1")?);
             Ok(Container::new())
         })
     ])?;
@@ -262,11 +380,39 @@ fn test_nodes_topo() -> QupidoResult {
     Ok(())
 }
 
+#[test]
+fn test_nodes_from_functions() -> QupidoResult {
+    fn plus_times(a: &u32, b: &u32) -> (u32, u32) {
+        (a + b, a * b)
+    }
+
+    fn square(v: &u32) -> u32 {
+        v * v
+    }
+
+    let pipeline = Pipeline::from_nodes(&[
+        crate::node!(plus_times("a", "b") -> ("a_plus_b", "a_times_b")).tag("math"),
+        crate::node!(square("a_plus_b") -> "squared")
+    ])?;
+    assert_eq!(pipeline.inputs(), vec![id("a"), id("b")]);
+    assert_eq!(pipeline.outputs(), vec![id("a_times_b"), id("squared")]);
+    assert_eq!(pipeline.node("plus_times")?.tags.len(), 1);
+
+    let mut container = Container::new();
+    container.insert("a", 3_u32)?;
+    container.insert("b", 5_u32)?;
+    let result = pipeline.run(&container)?;
+    assert_eq!(*result.get::<u32>("a_times_b")?, 15);
+    assert_eq!(*result.get::<u32>("squared")?, 64);
+
+    Ok(())
+}
+
 #[test]
 fn test_namespaces() -> QupidoResult {
 
-    pub fn num_calc<T>() -> QupidoResult<Pipeline<T>>
-        where T: Add<T, Output = T> + Debug + Clone + 'static
+    pub fn num_calc<T>() -> QupidoResult<Pipeline>
+        where T: Add<T, Output = T> + Debug + Clone + Send + Sync + 'static
     {
         let n = Node::new([id("x"), id("y")], [id("x+y")],
         |ctx| {
@@ -280,8 +426,8 @@ fn test_namespaces() -> QupidoResult {
         Pipeline::from_nodes(&[n])
     }
 
-    pub fn num_calc_map<T>() -> QupidoResult<Pipeline<T>>
-        where T: Add<T, Output = T> + Debug + Clone + 'static
+    pub fn num_calc_map<T>() -> QupidoResult<Pipeline>
+        where T: Add<T, Output = T> + Debug + Clone + Send + Sync + 'static
     {
         let n = Node::new([(id("my_x"), id("x")), (id("my_y"), id("y"))], [(id("my_x+y"), id("x+y"))],
         |ctx| {
@@ -301,7 +447,7 @@ fn test_namespaces() -> QupidoResult {
     data.insert("y", 12 as i64)?;
 
     let data_result = x_y.run(&data)?;
-    assert_eq!(*data_result.get("x+y")?, 9 as i64);
+    assert_eq!(*data_result.get::<i64>("x+y")?, 9 as i64);
 
     let x_y_namespaced = num_calc::<i64>()?.with_namespace("calc")?;
     println!("namespaced={:#?}", x_y_namespaced);
@@ -313,7 +459,7 @@ fn test_namespaces() -> QupidoResult {
     data2.insert("calc.x", 5 as i64)?;
     data2.insert("calc.y", 10 as i64)?;
     let data2_result = x_y_namespaced.run(&data2)?;
-    assert_eq!(*data2_result.get("calc.x+y")?, 15);
+    assert_eq!(*data2_result.get::<i64>("calc.x+y")?, 15);
 
     {
         let x_y = num_calc_map::<i64>()?;
@@ -322,7 +468,7 @@ fn test_namespaces() -> QupidoResult {
         data.insert("y", 6 as i64)?;
     
         let data_result = x_y.run(&data)?;
-        assert_eq!(*data_result.get("x+y")?, 1 as i64);
+        assert_eq!(*data_result.get::<i64>("x+y")?, 1 as i64);
 
         let namespaced = x_y.with_namespace("foo")?;
         println!("namespaced: {:#?}", namespaced);
@@ -331,8 +477,243 @@ fn test_namespaces() -> QupidoResult {
         data.insert("foo.y", 6 as i64)?;
     
         let data_result = namespaced.run(&data)?;
-        assert_eq!(*data_result.get("foo.x+y")?, 1 as i64);
+        assert_eq!(*data_result.get::<i64>("foo.x+y")?, 1 as i64);
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_mixed_data_types() -> QupidoResult {
+    let n = Node::new([id("values"), id("scale"), id("label")], [id("report")], |ctx| {
+        let values: &Vec<i64> = ctx.inputs.get("values")?;
+        let scale: &u32 = ctx.inputs.get("scale")?;
+        let label: &String = ctx.inputs.get("label")?;
+
+        let total: i64 = values.iter().map(|v| v * *scale as i64).sum();
+        let mut r = Container::new();
+        r.insert("report", format!("{}: {}", label, total))?;
+        Ok(r)
+    });
+
+    let pipeline = Pipeline::from_nodes(&[n])?;
+    let mut container = Container::new();
+    container.insert("values", vec![1_i64, 2, 3])?;
+    container.insert("scale", 10_u32)?;
+    container.insert("label", "total".to_string())?;
+
+    let result = pipeline.run(&container)?;
+    assert_eq!(result.get::<String>("report")?, "total: 60");
+
+    Ok(())
+}
+
+#[test]
+fn test_filtering() -> QupidoResult {
+    use crate::node::pass;
+
+    // raw -> clean -> features -> model
+    //              \-> report_table -> report
+    let pipeline = Pipeline::from_nodes(&[
+        pass("cleaning", &["raw"], &["clean"]).tag("ingest"),
+        pass("featurize", &["clean"], &["features"]),
+        pass("train", &["features"], &["model"]).tag("ml"),
+        pass("summarize", &["clean"], &["report_table"]).tag("reporting"),
+        pass("render", &["report_table"], &["report"]).tag("reporting"),
+    ])?;
+
+    fn names(p: &Pipeline) -> Vec<String> {
+        let mut r: Vec<_> = p.nodes().iter().map(|n| n.name.clone()).collect();
+        r.sort();
+        r
+    }
+
+    let reporting = pipeline.only_nodes_with_tags(&["reporting"])?;
+    assert_eq!(names(&reporting), vec!["render", "summarize"]);
+    assert_eq!(reporting.inputs(), vec![id("clean")]);
+    assert_eq!(names(&pipeline.only_nodes_with_tags(&["ml", "ingest"])?), vec!["cleaning", "train"]);
+
+    assert_eq!(names(&pipeline.from_inputs(&[id("clean")])?), vec!["featurize", "render", "summarize", "train"]);
+    assert_eq!(names(&pipeline.to_outputs(&[id("features")])?), vec!["cleaning", "featurize"]);
+    assert_eq!(names(&pipeline.from_nodes_named(&["summarize"])?), vec!["render", "summarize"]);
+    assert_eq!(names(&pipeline.to_nodes(&["render", "featurize"])?), vec!["cleaning", "featurize", "render", "summarize"]);
+
+    let mut container = Container::new();
+    container.insert("clean", 10_i64)?;
+    let result = pipeline.from_inputs(&[id("clean")])?.to_outputs(&[id("report")])?.run(&container)?;
+    assert_eq!(*result.get::<i64>("report")?, 12);
+    assert!(result.get::<i64>("model").is_err());
+
+    assert!(matches!(pipeline.to_outputs(&[id("nothing")]), Err(QupidoError::DataNotFound(_))));
+    match pipeline.from_nodes_named(&["nobody"]) {
+        Err(QupidoError::NodeNotFound(name)) => assert_eq!(name, "nobody"),
+        other => panic!("unexpected result: {:?}", other.map(|p| p.nodes().len())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_node_names() -> QupidoResult {
+    fn sum() -> Node {
+        Node::new([id("x"), id("y")], [id("sum")], |ctx| {
+            let x: &i64 = ctx.inputs.get("x")?;
+            let y: &i64 = ctx.inputs.get("y")?;
+            let mut r = Container::new();
+            r.insert("sum", x + y)?;
+            Ok(r)
+        })
+    }
+
+    let mapped = Node::new([(id("v"), id("sum")), (id("factor"), id("f"))], [id("scaled")], |_| Ok(Container::new()));
+    assert_eq!(sum().name, "[x,y] -> [sum]");
+    assert_eq!(mapped.name, "[f,sum] -> [scaled]");
+
+    let pipeline = Pipeline::from_nodes(&[sum().name("add"), mapped])?;
+    assert_eq!(pipeline.node("add")?.inputs.inputs(), vec![id("x"), id("y")]);
+    assert!(matches!(pipeline.node("nobody"), Err(QupidoError::NodeNotFound(name)) if name == "nobody"));
+
+    let namespaced = pipeline.with_namespace("eu")?;
+    assert_eq!(namespaced.node("eu.add")?.inputs.pairs(), vec![(id("x"), id("eu.x")), (id("y"), id("eu.y"))]);
+    assert!(namespaced.node("eu.[f,sum] -> [scaled]").is_ok());
+
+    let a = Node::new([id("a")], [id("b")], |_| Ok(Container::new())).name("step");
+    let b = Node::new([id("b")], [id("c")], |_| Ok(Container::new())).name("step");
+    assert!(matches!(Pipeline::from_nodes(&[a, b]), Err(QupidoError::DuplicateNode(name)) if name == "step"));
+
+    // unnamed nodes with the same sources are told apart by their ids
+    let sink = || Node::new([id("a")], (), |_| Ok(Container::new()));
+    let (first, second) = (sink(), sink());
+    let sinks = Pipeline::from_nodes(&[first.clone(), second.clone()])?;
+    assert_eq!(sinks.node("[a] -> []")?.id, first.id);
+    assert_eq!(sinks.node(&format!("[a] -> [] ({})", &second.id.simple().to_string()[..8]))?.id, second.id);
+    assert_eq!(sinks.with_namespace("eu")?.nodes().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_pipelines_cross_threads() -> QupidoResult {
+    use std::sync::{Arc, OnceLock};
+    use crate::registry::PipelineRegistry;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Node>();
+    assert_send_sync::<Pipeline>();
+    assert_send_sync::<Container>();
+    assert_send_sync::<Runner>();
+    assert_send_sync::<PipelineRegistry>();
+
+    static REGISTRY: OnceLock<PipelineRegistry> = OnceLock::new();
+
+    let double = Node::new([id("x")], [id("y")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let mut r = Container::new();
+        r.insert("y", x * 2)?;
+        Ok(r)
+    });
+    let pipeline = Arc::new(Pipeline::from_nodes(&[double])?);
+    let _ = REGISTRY.set(PipelineRegistry::new().with("double", pipeline.as_ref().clone())?);
+
+    let handles: Vec<_> = (0..4_i64).map(|i| {
+        let pipeline = pipeline.clone();
+        std::thread::spawn(move || -> QupidoResult<i64> {
+            let mut c = Container::new();
+            c.insert("x", i)?;
+            let shared = pipeline.run(&c)?;
+            let global = REGISTRY.get().unwrap().get("double")?.run(&c)?;
+            Ok(shared.get::<i64>("y")? + global.get::<i64>("y")?)
+        })
+    }).collect();
+    let results = handles.into_iter()
+        .map(|h| h.join().expect("pipeline thread panicked"))
+        .collect::<QupidoResult<Vec<_>>>()?;
+    assert_eq!(results, vec![0, 4, 8, 12]);
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_declared_types() -> QupidoResult {
+    use crate::source::SourceKey;
+
+    const COUNT: SourceKey<u32> = SourceKey::new("count");
+    const LABEL: SourceKey<String> = SourceKey::new("label");
+
+    fn count(x: &u32) -> u32 {
+        *x
+    }
+
+    fn label(count: &u32) -> String {
+        count.to_string()
+    }
+
+    // typed keys declare the types, the pipeline checks out
+    let pipeline = Pipeline::from_nodes(&[crate::node!(count("x") -> COUNT), crate::node!(label(COUNT) -> LABEL)])?;
+    pipeline.validate()?;
+    assert_eq!(pipeline.nodes[0].output_types["count"].name, "u32");
+
+    let produce = Node::new([id("x")], [(id("out"), id("a")), (id("other"), id("b"))], |ctx| {
+        let x: &u32 = ctx.inputs.get("x")?;
+        let mut r = Container::new();
+        r.insert("out", *x)?;
+        r.insert("other", x.to_string())?;
+        Ok(r)
+    }).output_type::<u32>("out").output_type::<String>("other").name("produce");
+    let consume = Node::new([(id("in"), id("a")), (id("text"), id("b"))], (), |_| Ok(Container::new()))
+        .input_type::<i64>("in").input_type::<f64>("text").name("consume");
+    let untyped = Node::new([id("a")], [id("c")], |_| Ok(Container::new())).name("untyped");
+    let pipeline = Pipeline::from_nodes(&[produce.clone(), consume, untyped])?;
+
+    let mismatches = match pipeline.validate() {
+        Err(QupidoError::TypeMismatches(errors)) => errors,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    let mut found: Vec<_> = mismatches.iter().map(|e| match e {
+        QupidoError::DataTypeMismatch { id, requested, stored } => (id.as_str(), *requested, *stored),
+        other => panic!("unexpected error: {:?}", other),
+    }).collect();
+    found.sort_by_key(|(id, _, _)| id.to_string());
+    assert_eq!(found, vec![
+        ("a", DataType::of::<i64>(), DataType::of::<u32>()),
+        ("b", DataType::of::<f64>(), DataType::of::<String>())
+    ]);
+
+    let mut container = Container::new();
+    container.insert("x", 3_u32)?;
+    assert!(matches!(pipeline.run(&container), Err(QupidoError::TypeMismatches(e)) if e.len() == 2));
+
+    // a value of another type than declared fails the producing node
+    let wrong = produce.output_type::<i64>("out");
+    match Pipeline::from_nodes(&[wrong])?.run(&container) {
+        Err(QupidoError::NodeFailed { node, source, .. }) => {
+            assert_eq!(node, "produce");
+            assert!(matches!(source.downcast_ref::<QupidoError>(), Some(QupidoError::DataTypeMismatch { id, .. }) if id == "a"));
+        },
+        other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_inputs_and_parameters() -> QupidoResult {
+    use crate::{id, param, node::pass};
+
+    let pipeline = Pipeline::from_nodes(&[
+        pass("scale", &["x", "params:factor"], &["y"]),
+        pass("shift", &["x", "y", "params:factor", "params:offset"], &["z"]),
+    ])?;
+
+    // every consumed source no node produces, parameters included
+    let produced = pipeline.all_outputs();
+    let mut free: Vec<_> = pipeline.nodes().iter().flat_map(|n| n.inputs.inputs()).filter(|s| !produced.contains(s)).collect();
+    free.sort();
+    assert_eq!(free, vec![id("x"), id("x"), param("factor"), param("factor"), param("offset")]);
+
+    free.dedup();
+    assert_eq!(pipeline.inputs(), free.iter().filter(|s| !s.is_param()).cloned().collect::<Vec<_>>());
+    assert_eq!(pipeline.inputs(), vec![id("x")]);
+    assert_eq!(pipeline.parameters(), vec![param("factor"), param("offset")]);
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{pipeline::Pipeline, Source, QupidoResult, QupidoError};
+
+/// Name of the pipeline run when none is given. Unless set with
+/// [`PipelineRegistry::set_default`], it is the sum of all other registered
+/// pipelines.
+pub const DEFAULT_PIPELINE: &str = "__default__";
+
+/// The named pipelines of a project, e.g. for the `qupido` command.
+///
+/// Pipelines are checked against each other when they are registered: a
+/// pipeline whose nodes would overwrite the output of already registered
+/// ones is rejected right away with `DuplicateData`, instead of when the
+/// default pipeline is run. More conflicts at once, or a cycle, are an
+/// `InvalidPipeline` error, as with [`Pipeline::from_nodes`].
+#[derive(Clone, Debug, Default)]
+pub struct PipelineRegistry {
+    pipelines: BTreeMap<String, Pipeline>,
+    explicit_default: bool
+}
+
+impl PipelineRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `pipeline` under `name`, failing with `DuplicatePipeline`
+    /// if the name is taken. That includes [`DEFAULT_PIPELINE`] as soon as
+    /// any pipeline is registered; replace it with [`PipelineRegistry::set_default`].
+    pub fn register(&mut self, name: impl Into<String>, pipeline: Pipeline) -> QupidoResult {
+        let name = name.into();
+        if self.pipelines.contains_key(&name) {
+            return Err(QupidoError::DuplicatePipeline(name));
+        }
+        if name == DEFAULT_PIPELINE {
+            self.set_default(pipeline);
+            return Ok(());
+        }
+
+        let others = self.pipelines.iter().filter(|(n, _)| *n != DEFAULT_PIPELINE).map(|(_, p)| p);
+        let default = sum(others.chain([&pipeline]))?;
+        self.pipelines.insert(name, pipeline);
+        if !self.explicit_default {
+            self.pipelines.insert(DEFAULT_PIPELINE.to_string(), default);
+        }
+        Ok(())
+    }
+
+    pub fn with(mut self, name: impl Into<String>, pipeline: Pipeline) -> QupidoResult<Self> {
+        self.register(name, pipeline)?;
+        Ok(self)
+    }
+
+    /// Runs `pipeline` when no pipeline is named, instead of the sum of all
+    /// registered ones, replacing the default pipeline there is so far.
+    /// Pipelines registered later are no longer added to it.
+    pub fn set_default(&mut self, pipeline: Pipeline) {
+        self.pipelines.insert(DEFAULT_PIPELINE.to_string(), pipeline);
+        self.explicit_default = true;
+    }
+
+    /// Registers the sum of already registered pipelines under a new name.
+    pub fn compose(&mut self, name: impl Into<String>, parts: &[&str]) -> QupidoResult {
+        let parts = parts.iter().map(|p| self.get(p)).collect::<QupidoResult<Vec<_>>>()?;
+        let pipeline = sum(parts.into_iter())?;
+        self.register(name, pipeline)
+    }
+
+    pub fn get(&self, name: &str) -> QupidoResult<&Pipeline> {
+        self.pipelines.get(name).ok_or_else(|| QupidoError::PipelineNotFound(name.to_string()))
+    }
+
+    /// Registered names in alphabetical order, including the default pipeline.
+    pub fn names(&self) -> Vec<&str> {
+        self.pipelines.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn inputs(&self, name: &str) -> QupidoResult<Vec<Source>> {
+        Ok(self.get(name)?.inputs())
+    }
+
+    pub fn outputs(&self, name: &str) -> QupidoResult<Vec<Source>> {
+        Ok(self.get(name)?.outputs())
+    }
+}
+
+/// Adds pipelines up, keeping nodes they share only once.
+fn sum<'a>(pipelines: impl Iterator<Item = &'a Pipeline>) -> QupidoResult<Pipeline> {
+    let mut seen = HashSet::new();
+    let mut nodes = vec![];
+    for p in pipelines {
+        for n in p.nodes() {
+            if seen.insert(n.id) {
+                nodes.push(n.clone());
+            }
+        }
+    }
+
+    Pipeline::from_nodes(&nodes)
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_registry() -> QupidoResult {
+    use crate::{id, node::pass};
+
+    let clean = pass("clean", &["raw"], &["clean"]);
+    let mut registry = PipelineRegistry::new();
+    registry.register("ingest", Pipeline::from_nodes(std::slice::from_ref(&clean))?)?;
+    registry.register("features", Pipeline::from_nodes(&[clean, pass("featurize", &["clean"], &["features"])])?)?;
+    registry.register("reporting", Pipeline::from_nodes(&[pass("report", &["features"], &["report"])])?)?;
+
+    assert_eq!(registry.names(), vec!["__default__", "features", "ingest", "reporting"]);
+    assert_eq!(registry.get(DEFAULT_PIPELINE)?.nodes().len(), 3);
+    assert_eq!(registry.inputs(DEFAULT_PIPELINE)?, vec![id("raw")]);
+    assert_eq!(registry.outputs(DEFAULT_PIPELINE)?, vec![id("report")]);
+    assert_eq!(registry.inputs("reporting")?, vec![id("features")]);
+
+    registry.compose("ingest_and_report", &["ingest", "reporting"])?;
+    assert_eq!(registry.inputs("ingest_and_report")?, vec![id("features"), id("raw")]);
+
+    // a second producer of `clean` would break the default pipeline
+    let legacy = registry.register("legacy", Pipeline::from_nodes(&[pass("legacy_clean", &["raw_v1"], &["clean"])])?);
+    assert!(matches!(legacy, Err(QupidoError::DuplicateData(id)) if id == "clean"));
+    assert!(registry.get("legacy").is_err());
+    assert!(matches!(registry.register("ingest", Pipeline::from_nodes(&[])?), Err(QupidoError::DuplicatePipeline(_))));
+    assert!(matches!(registry.compose("x", &["nope"]), Err(QupidoError::PipelineNotFound(name)) if name == "nope"));
+
+    assert!(matches!(registry.register(DEFAULT_PIPELINE, Pipeline::from_nodes(&[])?), Err(QupidoError::DuplicatePipeline(_))));
+    registry.set_default(registry.get("ingest")?.clone());
+    registry.register("more", Pipeline::from_nodes(&[pass("more", &["report"], &["more"])])?)?;
+    assert_eq!(registry.get(DEFAULT_PIPELINE)?.nodes().len(), 1);
+
+    Ok(())
+}
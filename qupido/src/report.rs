@@ -0,0 +1,403 @@
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{container::{Container, ContainerData}, hooks::Hook, html::{HtmlReport, NodeRun}, node::Node, pipeline::Pipeline, runner::Runner, Source, QupidoResult, QupidoError};
+
+/// What happened in a run: when it ran, how every node fared and which
+/// datasets the catalog loaded and saved. A report is produced for failed
+/// runs too, and serializes to JSON for archiving and comparing runs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub run_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// The error the run failed with, if it did.
+    pub error: Option<String>,
+    /// Every node of the pipeline, in the order they were scheduled.
+    pub nodes: Vec<NodeReport>,
+    pub loaded: Vec<String>,
+    pub saved: Vec<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeReport {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub status: NodeStatus,
+    /// How long the node function ran, unknown if it failed.
+    pub duration: Option<Duration>,
+    /// The sources the node returned.
+    pub outputs: Vec<String>,
+    /// Size of the outputs of a type the runner has [`OutputSizes`] for, by
+    /// source.
+    #[serde(default)]
+    pub output_sizes: BTreeMap<String, u64>,
+    pub error: Option<String>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeStatus {
+    Succeeded,
+    Failed,
+    /// Not run because the run failed before getting to the node.
+    Skipped
+}
+
+/// A size measured for a node output, see [`OutputSizes`].
+pub type SizeFuture = BoxFuture<'static, QupidoResult<u64>>;
+
+type SizeFn = dyn Fn(&dyn ContainerData) -> SizeFuture + Send + Sync;
+
+/// How to measure node outputs for run reports, by type: rows for a
+/// DataFrame, elements for a list, whatever fits the data. Outputs of other
+/// types get no size.
+///
+/// Sizes are futures as measuring may mean running a query. The report of
+/// [`Runner::run_async_with_report`] waits for them after the run, the one
+/// of [`Runner::run_with_report`] only has the sizes that are ready at once.
+#[derive(Clone, Default)]
+pub struct OutputSizes {
+    types: HashMap<TypeId, Arc<SizeFn>>
+}
+
+impl OutputSizes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T>(&mut self, size: impl Fn(&T) -> SizeFuture + Send + Sync + 'static)
+        where T: ContainerData
+    {
+        let size = move |v: &dyn ContainerData| size(v.downcast_ref::<T>().expect("registered by type id"));
+        self.types.insert(TypeId::of::<T>(), Arc::new(size));
+    }
+
+    pub fn with<T>(mut self, size: impl Fn(&T) -> SizeFuture + Send + Sync + 'static) -> Self
+        where T: ContainerData
+    {
+        self.register::<T>(size);
+        self
+    }
+
+    fn measure(&self, value: &dyn ContainerData) -> Option<SizeFuture> {
+        self.types.get(&value.as_any().type_id()).map(|size| size(value))
+    }
+}
+
+impl Debug for OutputSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputSizes").field("types", &self.types.len()).finish()
+    }
+}
+
+impl RunReport {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn node(&self, name: &str) -> Option<&NodeReport> {
+        self.nodes.iter().find(|n| n.name == name)
+    }
+
+    pub fn to_json(&self) -> QupidoResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> QupidoResult<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+impl Runner {
+    /// Runs the pipeline like [`Runner::run`], also reporting on the run,
+    /// whether it succeeded or not. The report has the run id the
+    /// `pipeline_run` span carries.
+    ///
+    /// With a [history](Runner::with_history), the report is saved to it.
+    pub fn run_with_report(&self, pipeline: &Pipeline, container: &Container) -> (QupidoResult<Container>, RunReport) {
+        let (result, report) = self.run_with_report_as(Uuid::new_v4(), pipeline, container);
+        self.keep_report(&report);
+        (result, report)
+    }
+
+    /// Runs the pipeline like [`Runner::run_async`], also reporting on the
+    /// run, whether it succeeded or not.
+    pub async fn run_async_with_report(&self, pipeline: &Pipeline, container: &Container) -> (QupidoResult<Container>, RunReport) {
+        let (result, report) = self.run_async_with_report_as(Uuid::new_v4(), pipeline, container).await;
+        self.keep_report(&report);
+        (result, report)
+    }
+
+    pub(crate) fn run_with_report_as(&self, run_id: Uuid, pipeline: &Pipeline, container: &Container) -> (QupidoResult<Container>, RunReport) {
+        let recorder = Arc::new(Recorder::new(self.output_sizes().cloned()));
+        let started_at = Utc::now();
+        let result = self.clone().with_hook(recorder.clone()).checkpointing(run_id).run_as(run_id, pipeline, container);
+        recorder.measure_now();
+        let report = recorder.report(pipeline, run_id, started_at, &result);
+        (result, report)
+    }
+
+    pub(crate) async fn run_async_with_report_as(&self, run_id: Uuid, pipeline: &Pipeline, container: &Container) -> (QupidoResult<Container>, RunReport) {
+        let recorder = Arc::new(Recorder::new(self.output_sizes().cloned()));
+        let started_at = Utc::now();
+        let result = self.clone().with_hook(recorder.clone()).checkpointing(run_id).run_async_as(run_id, pipeline, container).await;
+        recorder.measure().await;
+        let report = recorder.report(pipeline, run_id, started_at, &result);
+        (result, report)
+    }
+}
+
+impl Pipeline {
+    /// Runs the pipeline sequentially, also reporting on the run.
+    pub fn run_with_report(&self, container: &Container) -> (QupidoResult<Container>, RunReport) {
+        Runner::sequential().run_with_report(self, container)
+    }
+}
+
+impl<'p> HtmlReport<'p> {
+    /// Adds the durations and output sizes of the nodes that ran in `report`.
+    pub fn with_run_report(mut self, report: &RunReport) -> Self {
+        for n in &report.nodes {
+            if let Some(duration) = n.duration {
+                self = self.with_node_run(n.name.as_str(), NodeRun { duration, output_sizes: n.output_sizes.clone() });
+            }
+        }
+        self
+    }
+}
+
+/// Collects what the runner reports to its hooks during a run.
+struct Recorder {
+    sizes: Option<Arc<OutputSizes>>,
+    nodes: Mutex<HashMap<String, NodeReport>>,
+    loaded: Mutex<Vec<String>>,
+    saved: Mutex<Vec<String>>,
+    /// Output sizes still being measured, with the node and source they are for.
+    measuring: Mutex<Vec<(String, String, SizeFuture)>>
+}
+
+impl Recorder {
+    fn new(sizes: Option<Arc<OutputSizes>>) -> Self {
+        Recorder {
+            sizes,
+            nodes: Mutex::default(),
+            loaded: Mutex::default(),
+            saved: Mutex::default(),
+            measuring: Mutex::default()
+        }
+    }
+
+    /// Waits for the output sizes still being measured.
+    async fn measure(&self) {
+        let measuring = std::mem::take(&mut *self.measuring.lock().expect("recorder lock"));
+        let sizes = future::join_all(measuring.into_iter().map(|(node, output, size)| size.map(|size| (node, output, size)))).await;
+        self.record_sizes(sizes);
+    }
+
+    /// Like [`Recorder::measure`], but drops the sizes that aren't ready yet.
+    fn measure_now(&self) {
+        let measuring = std::mem::take(&mut *self.measuring.lock().expect("recorder lock"));
+        let sizes = measuring.into_iter()
+            .filter_map(|(node, output, size)| size.now_or_never().map(|size| (node, output, size)))
+            .collect();
+        self.record_sizes(sizes);
+    }
+
+    fn record_sizes(&self, sizes: Vec<(String, String, QupidoResult<u64>)>) {
+        let mut nodes = self.nodes.lock().expect("recorder lock");
+        for (node, output, size) in sizes {
+            match (size, nodes.get_mut(&node)) {
+                (Ok(size), Some(report)) => {
+                    report.output_sizes.insert(output, size);
+                },
+                (Ok(_), None) => (),
+                (Err(e), _) => warn!(node = %node, output = %output, error = %e, "could not measure output"),
+            }
+        }
+    }
+
+    fn report(&self, pipeline: &Pipeline, run_id: Uuid, started_at: DateTime<Utc>, result: &QupidoResult<Container>) -> RunReport {
+        let mut recorded = self.nodes.lock().expect("recorder lock");
+        let nodes = pipeline.nodes().iter()
+            .map(|n| recorded.remove(&n.name).unwrap_or_else(|| node_report(n, NodeStatus::Skipped)))
+            .collect();
+
+        RunReport {
+            run_id,
+            started_at,
+            finished_at: Utc::now(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            nodes,
+            loaded: self.loaded.lock().expect("recorder lock").clone(),
+            saved: self.saved.lock().expect("recorder lock").clone()
+        }
+    }
+}
+
+impl Hook for Recorder {
+    fn after_node_run(&self, node: &Node, outputs: &Container, duration: Duration) -> QupidoResult {
+        let mut report = node_report(node, NodeStatus::Succeeded);
+        report.duration = Some(duration);
+        let mut measuring = self.measuring.lock().expect("recorder lock");
+        for (local, global) in node.outputs.pairs() {
+            let Some(value) = outputs.data.get(&local.get_id()) else {
+                continue;
+            };
+            report.outputs.push(global.get_id());
+            if let Some(size) = self.sizes.as_ref().and_then(|sizes| sizes.measure(value.as_ref())) {
+                measuring.push((node.name.clone(), global.get_id(), size));
+            }
+        }
+        self.nodes.lock().expect("recorder lock").insert(node.name.clone(), report);
+        Ok(())
+    }
+
+    fn on_node_error(&self, node: &Node, error: &QupidoError) {
+        let mut nodes = self.nodes.lock().expect("recorder lock");
+        let report = nodes.entry(node.name.clone()).or_insert_with(|| node_report(node, NodeStatus::Failed));
+        report.status = NodeStatus::Failed;
+        report.error = Some(match std::error::Error::source(error) {
+            Some(cause) => cause.to_string(),
+            None => error.to_string()
+        });
+    }
+
+    fn after_dataset_loaded(&self, source: &Source) {
+        self.loaded.lock().expect("recorder lock").push(source.get_id());
+    }
+
+    fn after_dataset_saved(&self, _node: &Node, source: &Source) {
+        self.saved.lock().expect("recorder lock").push(source.get_id());
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("sizes", &self.sizes)
+            .field("nodes", &self.nodes)
+            .field("loaded", &self.loaded)
+            .field("saved", &self.saved)
+            .finish_non_exhaustive()
+    }
+}
+
+fn node_report(node: &Node, status: NodeStatus) -> NodeReport {
+    NodeReport {
+        name: node.name.clone(),
+        namespace: node.namespace.clone(),
+        status,
+        duration: None,
+        outputs: vec![],
+        output_sizes: BTreeMap::new(),
+        error: None
+    }
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_run_report() -> QupidoResult {
+    use crate::{id, catalog::{DataCatalog, MemoryDataset}};
+
+    #[derive(Debug)]
+    struct Saved;
+
+    impl crate::catalog::Dataset for Saved {
+        fn load(&self) -> crate::catalog::DatasetFuture<'_, Arc<dyn crate::container::ContainerData>> {
+            Box::pin(async { Err(QupidoError::DatasetError("write only".to_string())) })
+        }
+
+        fn save(&self, _data: Arc<dyn crate::container::ContainerData>) -> crate::catalog::DatasetFuture<'_> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn exists(&self) -> crate::catalog::DatasetFuture<'_, bool> {
+            Box::pin(async { Ok(false) })
+        }
+    }
+
+    let double = Node::new([id("x")], [id("doubled")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        if *x < 0 {
+            return Err(QupidoError::external("x must not be negative"));
+        }
+        let mut r = Container::new();
+        r.insert("doubled", x * 2)?;
+        Ok(r)
+    }).name("double");
+    let report_node = Node::new([id("doubled")], [id("report")], |ctx| {
+        let v: &i64 = ctx.inputs.get("doubled")?;
+        let mut r = Container::new();
+        r.insert("report", format!("{}", v))?;
+        Ok(r)
+    }).name("report");
+    let pipeline = Pipeline::from_nodes(&[double, report_node])?;
+    let catalog = DataCatalog::new()
+        .with(id("x"), MemoryDataset::with_data(4_i64))?
+        .with(id("report"), Saved)?;
+
+    let (result, report) = Runner::parallel(2).with_catalog(Arc::new(catalog)).run_with_report(&pipeline, &Container::new());
+    assert_eq!(result?.get::<String>("report")?, "8");
+    assert!(report.succeeded());
+    assert!(report.started_at <= report.finished_at);
+    assert_eq!(report.loaded, vec!["x"]);
+    assert_eq!(report.saved, vec!["report"]);
+    let double = report.node("double").unwrap();
+    assert_eq!((double.status, double.outputs.clone()), (NodeStatus::Succeeded, vec!["doubled".to_string()]));
+    assert!(double.duration.is_some());
+    assert_eq!(RunReport::from_json(&report.to_json()?)?, report);
+
+    // sizes that take a while are only waited for by the async report
+    let sizes = OutputSizes::new()
+        .with::<String>(|s| future::ready(Ok(s.len() as u64)).boxed())
+        .with::<i64>(|v| {
+            let (v, mut polled) = (*v, false);
+            future::poll_fn(move |cx| {
+                if std::mem::replace(&mut polled, true) {
+                    return std::task::Poll::Ready(Ok(v.unsigned_abs()));
+                }
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }).boxed()
+        });
+    let mut container = Container::new();
+    container.insert("x", 50_i64)?;
+    let runner = Runner::sequential().with_output_sizes(Arc::new(sizes));
+    let (_, report) = runner.run_with_report(&pipeline, &container);
+    assert!(report.node("double").unwrap().output_sizes.is_empty());
+    assert_eq!(report.node("report").unwrap().output_sizes, BTreeMap::from([("report".to_string(), 3)]));
+    let (_, report) = futures::executor::block_on(runner.run_async_with_report(&pipeline, &container));
+    assert_eq!(report.node("double").unwrap().output_sizes, BTreeMap::from([("doubled".to_string(), 100)]));
+    assert!(report.to_json()?.contains("\"output_sizes\": {\n        \"report\": 3\n      }"));
+    let html = HtmlReport::new(&pipeline).with_run_report(&report).render();
+    assert!(html.contains("\"output_sizes\":{\"doubled\":100}"));
+    assert_eq!(RunReport::from_json(&report.to_json()?)?, report);
+
+    let mut negative = Container::new();
+    negative.insert("x", -1_i64)?;
+    let (result, report) = pipeline.run_with_report(&negative);
+    assert!(result.is_err());
+    assert!(report.error.as_deref().unwrap().contains("x must not be negative"));
+    assert_eq!(report.nodes.iter().map(|n| n.status).collect::<Vec<_>>(), vec![NodeStatus::Failed, NodeStatus::Skipped]);
+    assert_eq!(report.nodes[0].error.as_deref(), Some("x must not be negative"));
+    assert!(report.to_json()?.contains("\"status\": \"skipped\""));
+
+    Ok(())
+}
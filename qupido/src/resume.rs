@@ -0,0 +1,308 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{cache::NodeCache, container::Container, pipeline::Pipeline, report::{NodeStatus, RunReport}, runner::Runner, QupidoResult, QupidoError};
+
+/// The key node outputs are checkpointed under, in the directory of their run.
+pub(crate) const CHECKPOINT: &str = "outputs";
+
+/// Reports of past runs, kept as one JSON file per run id so failed runs can
+/// be resumed later.
+///
+/// Next to the report of a run, a directory named after the run id holds
+/// checkpoints of the node outputs that aren't saved to the catalog. They are
+/// stored like [`NodeCache`] entries, so only outputs of the parameter types
+/// and of the types of [`RunHistory::with_checkpoint_codecs`] are kept.
+/// Checkpoints are removed once a run succeeded.
+#[derive(Clone, Debug)]
+pub struct RunHistory {
+    dir: PathBuf,
+    codecs: NodeCache
+}
+
+impl RunHistory {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        RunHistory {
+            codecs: NodeCache::new(&dir),
+            dir
+        }
+    }
+
+    /// Checkpoints the types registered with `codecs` too.
+    pub fn with_checkpoint_codecs(mut self, codecs: &NodeCache) -> Self {
+        self.codecs = codecs.clone();
+        self
+    }
+
+    pub fn save(&self, report: &RunReport) -> QupidoResult {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(report.run_id), report.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(&self, run_id: Uuid) -> QupidoResult<RunReport> {
+        let path = self.path(run_id);
+        if !path.exists() {
+            return Err(QupidoError::RunNotFound(run_id.to_string()));
+        }
+        RunReport::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// The checkpoints of the run `run_id`.
+    pub(crate) fn checkpoints(&self, run_id: Uuid) -> NodeCache {
+        self.codecs.clone().in_dir(self.dir.join(run_id.to_string()))
+    }
+
+    fn path(&self, run_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", run_id))
+    }
+}
+
+impl Pipeline {
+    /// The nodes still to run to finish the run `previous` reports on: every
+    /// node that didn't succeed, plus the succeeded ones whose outputs are
+    /// needed but were neither saved to the catalog nor `restored` from the
+    /// checkpoints of the run.
+    pub fn remaining(&self, previous: &RunReport, restored: &Container) -> QupidoResult<Pipeline> {
+        let done: HashSet<_> = previous.nodes.iter()
+            .filter(|n| n.status == NodeStatus::Succeeded)
+            .map(|n| n.name.as_str())
+            .collect();
+        let available: HashSet<_> = previous.saved.iter().chain(&previous.loaded).map(|s| s.as_str())
+            .chain(restored.data.keys().map(|k| k.as_str()))
+            .collect();
+        let mut rerun: HashSet<_> = self.nodes.iter()
+            .filter(|n| !done.contains(n.name.as_str()))
+            .map(|n| n.id)
+            .collect();
+
+        // producers come before their consumers, so walking backwards visits
+        // every producer pulled in after the consumer that needs it
+        for n in self.nodes.iter().rev() {
+            if !rerun.contains(&n.id) {
+                continue;
+            }
+            for input in n.inputs.inputs() {
+                if available.contains(input.get_id().as_str()) {
+                    continue;
+                }
+                for p in self.nodes.iter().filter(|p| p.outputs.outputs().contains(&input)) {
+                    rerun.insert(p.id);
+                }
+            }
+        }
+
+        let nodes: Vec<_> = self.nodes.iter().filter(|n| rerun.contains(&n.id)).cloned().collect();
+        Pipeline::from_nodes(&nodes)
+    }
+}
+
+impl Runner {
+    /// Continues the failed run `run_id` of the runner's
+    /// [history](Runner::with_history) with the nodes
+    /// [`Pipeline::remaining`] returns. Outputs of the nodes that succeeded
+    /// are restored from the checkpoints of the run, or loaded from the
+    /// catalog like any other input if it saved them.
+    ///
+    /// The resumed run keeps its run id, so it can be resumed again. Its
+    /// report covers the whole pipeline: nodes that didn't run again keep
+    /// their status from the previous report. Fails without running anything
+    /// if the run can't be found.
+    pub fn resume(&self, pipeline: &Pipeline, run_id: Uuid, container: &Container) -> QupidoResult<(QupidoResult<Container>, RunReport)> {
+        let (previous, remaining, state) = self.prepare_resume(pipeline, run_id, container)?;
+        let (result, report) = self.run_with_report_as(run_id, &remaining, &state);
+        let report = merge(pipeline, &previous, report);
+        self.keep_report(&report);
+        Ok((result, report))
+    }
+
+    /// Like [`Runner::resume`], running the remaining nodes with [`Runner::run_async`].
+    pub async fn resume_async(&self, pipeline: &Pipeline, run_id: Uuid, container: &Container) -> QupidoResult<(QupidoResult<Container>, RunReport)> {
+        let (previous, remaining, state) = self.prepare_resume(pipeline, run_id, container)?;
+        let (result, report) = self.run_async_with_report_as(run_id, &remaining, &state).await;
+        let report = merge(pipeline, &previous, report);
+        self.keep_report(&report);
+        Ok((result, report))
+    }
+
+    /// The report of the run, the nodes left to run and the container to run
+    /// them with.
+    fn prepare_resume(&self, pipeline: &Pipeline, run_id: Uuid, container: &Container) -> QupidoResult<(RunReport, Pipeline, Container)> {
+        let history = self.history()
+            .ok_or_else(|| QupidoError::ConfigError("the runner has no run history to resume from".to_string()))?;
+        let previous = history.load(run_id)?;
+
+        let checkpoints = history.checkpoints(run_id);
+        let mut restored = Container::new();
+        let succeeded = pipeline.nodes().iter()
+            .filter(|n| previous.node(&n.name).is_some_and(|r| r.status == NodeStatus::Succeeded));
+        for n in succeeded {
+            let Some(outputs) = checkpoints.entry(n, CHECKPOINT) else {
+                continue;
+            };
+            for (local, global) in n.outputs.pairs() {
+                if let Some(value) = outputs.data.get(&local.get_id()) {
+                    restored.data.insert(global.get_id(), value.clone());
+                }
+            }
+        }
+
+        let remaining = pipeline.remaining(&previous, &restored)?;
+        if let Some(failed) = previous.nodes.iter().find(|n| n.status == NodeStatus::Failed) {
+            info!(run_id = %run_id, node = %failed.name, nodes = remaining.nodes().len(), restored = restored.data.len(), "resuming run");
+        }
+        // only what the remaining nodes need goes into the run
+        let mut state = container.clone();
+        for input in remaining.inputs() {
+            let id = input.get_id();
+            if let (false, Some(value)) = (state.contains(&id), restored.data.get(&id)) {
+                state.data.insert(id, value.clone());
+            }
+        }
+        Ok((previous, remaining, state))
+    }
+
+    /// Starts checkpointing node outputs for the run `run_id`, if the runner
+    /// has a history.
+    pub(crate) fn checkpointing(self, run_id: Uuid) -> Self {
+        match self.history() {
+            Some(history) => {
+                let checkpoints = Arc::new(history.checkpoints(run_id));
+                self.with_checkpoints(checkpoints)
+            },
+            None => self
+        }
+    }
+
+    /// Saves the report to the runner's history, if it has one, and removes
+    /// the checkpoints of runs that succeeded. Failing to is logged, the run
+    /// itself is over.
+    pub(crate) fn keep_report(&self, report: &RunReport) {
+        let Some(history) = self.history() else {
+            return;
+        };
+        if let Err(e) = history.save(report) {
+            warn!(run_id = %report.run_id, error = %e, "could not save the run report");
+        }
+        if report.succeeded() {
+            if let Err(e) = history.checkpoints(report.run_id).clear() {
+                warn!(run_id = %report.run_id, error = %e, "could not remove the checkpoints of the run");
+            }
+        }
+    }
+}
+
+fn merge(pipeline: &Pipeline, previous: &RunReport, mut report: RunReport) -> RunReport {
+    report.nodes = pipeline.nodes().iter()
+        .filter_map(|n| report.node(&n.name).or_else(|| previous.node(&n.name)).cloned())
+        .collect();
+    for (old, new) in [(&previous.loaded, &mut report.loaded), (&previous.saved, &mut report.saved)] {
+        let mut merged: Vec<_> = old.iter().filter(|s| !new.contains(s)).cloned().collect();
+        merged.append(new);
+        *new = merged;
+    }
+    report
+}
+
+
+
+
+
+
+
+
+#[test]
+fn test_resume_failed_run() -> QupidoResult {
+    use std::sync::{Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}};
+    use crate::{id, catalog::{DataCatalog, Dataset, DatasetFuture}, container::ContainerData, node::Node};
+
+    #[derive(Debug, Default)]
+    struct Stored(Mutex<Option<Arc<dyn ContainerData>>>);
+
+    impl Dataset for Stored {
+        fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+            let data = self.0.lock().unwrap().clone();
+            Box::pin(async move { data.ok_or_else(|| QupidoError::DatasetError("nothing stored".to_string())) })
+        }
+
+        fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+            *self.0.lock().unwrap() = Some(data);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn exists(&self) -> DatasetFuture<'_, bool> {
+            let exists = self.0.lock().unwrap().is_some();
+            Box::pin(async move { Ok(exists) })
+        }
+    }
+
+    // `x` only lives in memory, `y` is saved to the catalog and `c` fails until fixed
+    let calls = Arc::new(AtomicUsize::new(0));
+    let broken = Arc::new(AtomicBool::new(true));
+    let (a_calls, b_calls, c_calls, c_broken) = (calls.clone(), calls.clone(), calls.clone(), broken.clone());
+    let pipeline = Pipeline::from_nodes(&[
+        Node::new(id("in"), id("x"), move |ctx| {
+            a_calls.fetch_add(1, Ordering::SeqCst);
+            let mut r = Container::new();
+            r.insert("x", ctx.inputs.get::<i64>("in")? + 1)?;
+            Ok(r)
+        }).name("a"),
+        Node::new(id("x"), id("y"), move |ctx| {
+            b_calls.fetch_add(1, Ordering::SeqCst);
+            let mut r = Container::new();
+            r.insert("y", ctx.inputs.get::<i64>("x")? * 10)?;
+            Ok(r)
+        }).name("b"),
+        Node::new([id("x"), id("y")], [id("z")], move |ctx| {
+            c_calls.fetch_add(1, Ordering::SeqCst);
+            if c_broken.load(Ordering::SeqCst) {
+                return Err(QupidoError::external("not yet"));
+            }
+            let mut r = Container::new();
+            r.insert("z", ctx.inputs.get::<i64>("x")? + ctx.inputs.get::<i64>("y")?)?;
+            Ok(r)
+        }).name("c")
+    ])?;
+
+    let dir = std::env::temp_dir().join(format!("qupido_runs_{}", std::process::id()));
+    let history = Arc::new(RunHistory::new(&dir));
+    let runner = Runner::sequential()
+        .with_catalog(Arc::new(DataCatalog::new().with(id("y"), Stored::default())?))
+        .with_history(history.clone());
+    let mut container = Container::new();
+    container.insert("in", 0_i64)?;
+
+    let (result, failed) = runner.run_with_report(&pipeline, &container);
+    assert!(result.is_err());
+    assert_eq!(failed.saved, vec!["y"]);
+    assert_eq!(history.load(failed.run_id)?, failed);
+    assert!(matches!(history.load(Uuid::nil()), Err(QupidoError::RunNotFound(_))));
+    assert!(matches!(runner.resume(&pipeline, Uuid::nil(), &container), Err(QupidoError::RunNotFound(_))));
+
+    // `x` is checkpointed and `y` saved, so only `c` runs again, as the same run
+    broken.store(false, Ordering::SeqCst);
+    calls.store(0, Ordering::SeqCst);
+    let (result, resumed) = runner.resume(&pipeline, failed.run_id, &container)?;
+    assert_eq!(*result?.get::<i64>("z")?, 11);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(resumed.succeeded());
+    assert_eq!(resumed.run_id, failed.run_id);
+    assert_eq!(resumed.nodes.iter().map(|n| n.status).collect::<Vec<_>>(), vec![NodeStatus::Succeeded; 3]);
+    assert_eq!(resumed.loaded, vec!["y"]);
+    assert_eq!(history.load(failed.run_id)?, resumed);
+    assert!(!dir.join(failed.run_id.to_string()).exists());
+
+    // without the checkpoint of `x`, `a` has to produce it again
+    assert_eq!(pipeline.remaining(&failed, &Container::new())?.nodes().iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), vec!["a", "c"]);
+    let mut unsaved = failed.clone();
+    unsaved.saved.clear();
+    assert_eq!(pipeline.remaining(&unsaved, &Container::new())?.nodes().len(), 3);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
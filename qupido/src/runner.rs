@@ -0,0 +1,947 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction;
+use uuid::Uuid;
+
+use futures::channel::oneshot;
+use futures::future::FutureExt;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tracing::{debug, dispatcher, error, field, info, info_span, Instrument, Span};
+
+use crate::{cache::NodeCache, catalog::DataCatalog, container::Container, hooks::Hook, parameters::Parameters, Source, node::{Node, NodeFunc, NodeFuture, SyncNodeFn}, pipeline::Pipeline, report::OutputSizes, resume::{RunHistory, CHECKPOINT}, Context, Tag, QupidoResult, QupidoError};
+
+/// Executes the nodes of a [`Pipeline`].
+///
+/// With a single worker the nodes run one after the other in topological
+/// order. With more workers, every node whose inputs are available is handed
+/// to a pool of threads, so independent branches of the graph run
+/// concurrently. Both modes produce the same resulting container.
+///
+/// Pipelines containing async nodes have to be run with [`Runner::run_async`].
+///
+/// A [`DataCatalog`] can be attached to load missing pipeline inputs and to
+/// save node outputs to their persistent datasets, and [`Parameters`] to
+/// provide the `params:` sources nodes depend on. [`Hook`]s attached with
+/// [`Runner::with_hook`] are called around the run and every node, in the
+/// order they were attached.
+///
+/// Runs are traced: a `pipeline_run` span, carrying a fresh run id, covers
+/// the whole run and a `node` span, carrying the node's name, namespace,
+/// tags, sources and duration, every node. Failures are logged as error
+/// events.
+///
+/// By default the resulting container holds every value of the run. With
+/// [`Runner::free_intermediates`], node outputs are dropped as soon as their
+/// last consumer ran, keeping peak memory down for large datasets.
+#[derive(Clone, Debug)]
+pub struct Runner {
+    workers: usize,
+    catalog: Option<Arc<DataCatalog>>,
+    parameters: Option<Arc<Parameters>>,
+    hooks: Vec<Arc<dyn Hook>>,
+    cache: Option<Arc<NodeCache>>,
+    sizes: Option<Arc<OutputSizes>>,
+    history: Option<Arc<RunHistory>>,
+    /// Where the run in progress keeps its checkpoints, if the runner has a history.
+    checkpoints: Option<Arc<NodeCache>>,
+    keep: Option<HashSet<Source>>
+}
+
+impl Runner {
+    pub fn sequential() -> Self {
+        Runner {
+            workers: 1,
+            catalog: None,
+            parameters: None,
+            hooks: vec![],
+            cache: None,
+            sizes: None,
+            history: None,
+            checkpoints: None,
+            keep: None
+        }
+    }
+
+    pub fn parallel(workers: usize) -> Self {
+        Runner {
+            workers: workers.max(1),
+            catalog: None,
+            parameters: None,
+            hooks: vec![],
+            cache: None,
+            sizes: None,
+            history: None,
+            checkpoints: None,
+            keep: None
+        }
+    }
+
+    /// Loads missing pipeline inputs from `catalog` before the run and saves
+    /// node outputs to it as they are produced.
+    ///
+    /// [`Runner::run`] drives the catalog's futures with a blocking executor
+    /// and fails with `AsyncDatasetInSyncRun` if the pipeline uses a dataset
+    /// that [needs an async runtime](crate::catalog::Dataset::needs_runtime),
+    /// like DataFusion's; run those with [`Runner::run_async`].
+    pub fn with_catalog(mut self, catalog: Arc<DataCatalog>) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    pub fn with_parameters(mut self, parameters: Arc<Parameters>) -> Self {
+        self.parameters = Some(parameters);
+        self
+    }
+
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Skips nodes whose outputs are in `cache`, see [`NodeCache`].
+    pub fn with_cache(mut self, cache: Arc<NodeCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Measures node outputs for the reports of
+    /// [`Runner::run_with_report`] and [`Runner::run_async_with_report`].
+    pub fn with_output_sizes(mut self, sizes: Arc<OutputSizes>) -> Self {
+        self.sizes = Some(sizes);
+        self
+    }
+
+    /// Saves the reports of [`Runner::run_with_report`] and
+    /// [`Runner::run_async_with_report`] to `history`, with checkpoints of the
+    /// node outputs the catalog doesn't save, so failed runs can be picked up
+    /// again with [`Runner::resume`].
+    pub fn with_history(mut self, history: Arc<RunHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub(crate) fn with_checkpoints(mut self, checkpoints: Arc<NodeCache>) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Drops node outputs from the run state once every node consuming them
+    /// ran. Outputs nobody consumes, pipeline inputs and the sources in `keep`
+    /// stay in the resulting container.
+    pub fn free_intermediates(mut self, keep: &[Source]) -> Self {
+        self.keep = Some(keep.iter().cloned().collect());
+        self
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn catalog(&self) -> Option<&Arc<DataCatalog>> {
+        self.catalog.as_ref()
+    }
+
+    pub fn output_sizes(&self) -> Option<&Arc<OutputSizes>> {
+        self.sizes.as_ref()
+    }
+
+    pub fn history(&self) -> Option<&Arc<RunHistory>> {
+        self.history.as_ref()
+    }
+
+    pub fn run(&self, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        self.run_as(Uuid::new_v4(), pipeline, container)
+    }
+
+    /// Runs the pipeline like [`Runner::run`] as the run `run_id`.
+    pub(crate) fn run_as(&self, run_id: Uuid, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        let span = pipeline_span(pipeline, self.workers, run_id);
+        let _entered = span.enter();
+        let start = Instant::now();
+        let result = self.run_blocking(pipeline, container);
+        self.finish(pipeline, result, start)
+    }
+
+    /// Runs the pipeline on the caller's async runtime. Async nodes are
+    /// awaited on it, sync nodes are called on threads of their own so they
+    /// don't block it; up to `workers` nodes of either kind are in flight at
+    /// the same time.
+    pub async fn run_async(&self, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        self.run_async_as(Uuid::new_v4(), pipeline, container).await
+    }
+
+    /// Runs the pipeline like [`Runner::run_async`] as the run `run_id`.
+    pub(crate) async fn run_async_as(&self, run_id: Uuid, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        let span = pipeline_span(pipeline, self.workers, run_id);
+        async {
+            let start = Instant::now();
+            let result = self.run_scheduled(pipeline, container).await;
+            self.finish(pipeline, result, start)
+        }.instrument(span).await
+    }
+
+    fn run_blocking(&self, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        check(pipeline)?;
+        if let Some(n) = pipeline.nodes.iter().find(|n| n.func.is_async()) {
+            return Err(QupidoError::AsyncNodeInSyncRun(n.name.clone()));
+        }
+        if let Some(catalog) = &self.catalog {
+            let mut used = pipeline.inputs().into_iter().chain(pipeline.all_outputs());
+            if let Some(s) = used.find(|s| catalog.get(s).is_some_and(|d| d.needs_runtime())) {
+                return Err(QupidoError::AsyncDatasetInSyncRun(s.get_id()));
+            }
+        }
+
+        self.hooks.iter().for_each(|h| h.before_pipeline_run(pipeline));
+        let mut state = container.clone();
+        self.insert_parameters(pipeline, &mut state)?;
+        if let Some(catalog) = &self.catalog {
+            let loaded = futures::executor::block_on(catalog.load_missing(&pipeline.inputs(), &mut state))?;
+            self.loaded(&loaded);
+        }
+
+        let state = if self.workers == 1 {
+            self.run_sequential(pipeline, state)?
+        } else {
+            self.run_parallel(pipeline, state)?
+        };
+        Ok(state)
+    }
+
+    async fn run_scheduled(&self, pipeline: &Pipeline, container: &Container) -> QupidoResult<Container> {
+        check(pipeline)?;
+        self.hooks.iter().for_each(|h| h.before_pipeline_run(pipeline));
+        let mut schedule = Schedule::new(pipeline);
+        let mut state = container.clone();
+        self.insert_parameters(pipeline, &mut state)?;
+        if let Some(catalog) = &self.catalog {
+            let loaded = catalog.load_missing(&pipeline.inputs(), &mut state).await?;
+            self.loaded(&loaded);
+        }
+        let mut first_error = None;
+        let mut in_flight = FuturesUnordered::new();
+        let mut release = self.release(pipeline);
+
+        loop {
+            while in_flight.len() < self.workers {
+                let Some(idx) = schedule.pop_ready() else {
+                    break;
+                };
+                let node = schedule.node(idx);
+                let span = node_span(node);
+                let ctx = span.in_scope(|| node.context(&state).and_then(|ctx| self.before_node_run(node, &ctx).map(|_| ctx)));
+                let ctx = match ctx {
+                    Ok(ctx) => ctx,
+                    Err(e) => {
+                        first_error.get_or_insert(span.in_scope(|| self.fail(node, e)));
+                        schedule.abort();
+                        break;
+                    }
+                };
+                let thread_span = span.clone();
+                // fingerprinting and caching may run queries, so they are part of the node's future
+                let fut = async move {
+                    let start = Instant::now();
+                    let (key, cached) = self.cached(node, &ctx).await;
+                    let res = match (cached, &node.func) {
+                        (Some(res), _) => Ok(res),
+                        (None, NodeFunc::Sync(f)) => call_on_thread(f.clone(), ctx, thread_span).await,
+                        (None, NodeFunc::Async(f)) => f(ctx).await,
+                    };
+                    let duration = start.elapsed();
+                    if let Ok(res) = &res {
+                        self.cache_outputs(node, key.as_deref(), res).await;
+                        self.checkpoint(node, res).await;
+                    }
+                    (res, duration)
+                };
+                in_flight.push(fut.instrument(span.clone()).map(move |(res, duration)| (idx, res, duration, span)));
+            }
+
+            let Some((idx, res, duration, span)) = in_flight.next().await else {
+                break;
+            };
+
+            let node = schedule.node(idx);
+            let mut stored = span.in_scope(|| res
+                .and_then(|res| self.after_node_run(node, &res, duration).map(|_| res))
+                .and_then(|res| node.store_outputs(res, &mut state)));
+            if let (Ok(()), Some(catalog)) = (&stored, &self.catalog) {
+                stored = catalog.save_persistent(&node.outputs.outputs(), &state)
+                    .instrument(span.clone())
+                    .await
+                    .map(|saved| self.saved(node, &saved));
+            }
+            if let (Ok(()), Some(release)) = (&stored, &mut release) {
+                release.consumed(node, &mut state);
+            }
+            match stored {
+                Ok(()) if first_error.is_none() => schedule.complete(idx),
+                Ok(()) => (),
+                Err(e) => {
+                    first_error.get_or_insert(span.in_scope(|| self.fail(node, e)));
+                    schedule.abort();
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(state)
+        }
+    }
+
+    fn run_sequential(&self, pipeline: &Pipeline, mut state: Container) -> QupidoResult<Container> {
+        let mut release = self.release(pipeline);
+        for n in &pipeline.nodes {
+            node_span(n).in_scope(|| n.context(&state)
+                .and_then(|ctx| self.call(n, &ctx))
+                .and_then(|res| n.store_outputs(res, &mut state))
+                .and_then(|_| self.save_outputs_blocking(n, &state))
+                .map_err(|e| self.fail(n, e)))?;
+            if let Some(release) = &mut release {
+                release.consumed(n, &mut state);
+            }
+        }
+
+        Ok(state)
+    }
+
+    fn run_parallel(&self, pipeline: &Pipeline, mut state: Container) -> QupidoResult<Container> {
+        let mut schedule = Schedule::new(pipeline);
+        let mut first_error = None;
+        let mut release = self.release(pipeline);
+        // workers log to the subscriber of the calling thread, even if it is not the global one
+        let dispatch = dispatcher::get_default(|d| d.clone());
+
+        thread::scope(|scope| {
+            let (job_tx, job_rx) = mpsc::channel::<(NodeIndex, &Node, Context, Span)>();
+            let job_rx = Arc::new(Mutex::new(job_rx));
+            let (done_tx, done_rx) = mpsc::channel();
+
+            for _ in 0..self.workers {
+                let job_rx = job_rx.clone();
+                let done_tx = done_tx.clone();
+                let dispatch = &dispatch;
+                scope.spawn(move || dispatcher::with_default(dispatch, || loop {
+                    let job = job_rx.lock().map_err(|_| ()).and_then(|rx| rx.recv().map_err(|_| ()));
+                    let Ok((idx, node, ctx, span)) = job else {
+                        break;
+                    };
+                    let res = span.in_scope(|| panic::catch_unwind(AssertUnwindSafe(|| self.call(node, &ctx))));
+                    if done_tx.send((idx, res, span)).is_err() {
+                        break;
+                    }
+                }));
+            }
+            drop(done_tx);
+
+            let mut running = 0;
+            loop {
+                while let Some(idx) = schedule.pop_ready() {
+                    let node = schedule.node(idx);
+                    let span = node_span(node);
+                    match node.context(&state) {
+                        Ok(ctx) => {
+                            // workers only exit once job_tx is dropped
+                            job_tx.send((idx, node, ctx, span)).expect("runner workers are alive");
+                            running += 1;
+                        },
+                        Err(e) => {
+                            first_error.get_or_insert(span.in_scope(|| self.fail(node, e)));
+                            schedule.abort();
+                        }
+                    }
+                }
+
+                if running == 0 {
+                    break;
+                }
+
+                let (idx, res, span) = done_rx.recv().expect("runner workers are alive");
+                running -= 1;
+
+                let res = match res {
+                    Ok(res) => res,
+                    Err(payload) => panic::resume_unwind(payload),
+                };
+                let node = schedule.node(idx);
+                let stored = span.in_scope(|| res
+                    .and_then(|res| node.store_outputs(res, &mut state))
+                    .and_then(|_| self.save_outputs_blocking(node, &state)));
+                if let (Ok(()), Some(release)) = (&stored, &mut release) {
+                    release.consumed(node, &mut state);
+                }
+                match stored {
+                    Ok(()) if first_error.is_none() => schedule.complete(idx),
+                    Ok(()) => (),
+                    Err(e) => {
+                        first_error.get_or_insert(span.in_scope(|| self.fail(node, e)));
+                        schedule.abort();
+                    }
+                }
+            }
+
+            drop(job_tx);
+        });
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(state)
+        }
+    }
+
+    /// Calls a sync node between the node hooks, unless its outputs are cached.
+    fn call(&self, node: &Node, ctx: &Context) -> QupidoResult<Container> {
+        self.before_node_run(node, ctx)?;
+        let start = Instant::now();
+        // codecs that aren't done at once need a runtime, whose values aren't cached here
+        let res = match self.cached(node, ctx).now_or_never().unwrap_or_default() {
+            (_, Some(res)) => res,
+            (key, None) => {
+                let res = node.call(ctx)?;
+                if self.cache_outputs(node, key.as_deref(), &res).now_or_never().is_none() {
+                    debug!("outputs need a runtime to be cached, not caching");
+                }
+                res
+            }
+        };
+        if self.checkpoint(node, &res).now_or_never().is_none() {
+            debug!("outputs need a runtime to be checkpointed, not checkpointing");
+        }
+        self.after_node_run(node, &res, start.elapsed())?;
+        Ok(res)
+    }
+
+    /// The cached outputs of the node for this context if there are any, or
+    /// else the key to cache them under once the node ran.
+    async fn cached(&self, node: &Node, ctx: &Context) -> (Option<String>, Option<Container>) {
+        let Some(cache) = &self.cache else {
+            return (None, None);
+        };
+        let Some(key) = cache.key(node, &ctx.inputs).await else {
+            return (None, None);
+        };
+        match cache.restore(node, &key) {
+            Some(outputs) => (None, Some(outputs)),
+            None => (Some(key), None)
+        }
+    }
+
+    async fn cache_outputs(&self, node: &Node, key: Option<&str>, outputs: &Container) {
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.store(node, key, outputs).await;
+        }
+    }
+
+    /// Keeps the outputs of the node the catalog doesn't save in the
+    /// checkpoints of the run, for when it's resumed.
+    async fn checkpoint(&self, node: &Node, outputs: &Container) {
+        let Some(checkpoints) = &self.checkpoints else {
+            return;
+        };
+        let mut kept = Container::new();
+        for (local, global) in node.outputs.pairs() {
+            let saved = self.catalog.as_ref().and_then(|c| c.get(&global)).is_some_and(|d| d.is_persistent());
+            if let (false, Some(value)) = (saved, outputs.data.get(&local.get_id())) {
+                kept.data.insert(local.get_id(), value.clone());
+            }
+        }
+        if !kept.data.is_empty() {
+            checkpoints.store(node, CHECKPOINT, &kept).await;
+        }
+    }
+
+    fn before_node_run(&self, node: &Node, ctx: &Context) -> QupidoResult {
+        info!("node started");
+        self.hooks.iter().try_for_each(|h| h.before_node_run(node, &ctx.inputs))
+    }
+
+    fn after_node_run(&self, node: &Node, outputs: &Container, duration: Duration) -> QupidoResult {
+        let duration_ms = duration.as_millis() as u64;
+        Span::current().record("duration_ms", duration_ms);
+        info!(duration_ms, "node finished");
+        self.hooks.iter().try_for_each(|h| h.after_node_run(node, outputs, duration))
+    }
+
+    /// Attaches the node to an error and reports it to the hooks.
+    fn fail(&self, node: &Node, e: QupidoError) -> QupidoError {
+        let e = node.failed(e);
+        error!(error = %e, "node failed");
+        self.hooks.iter().for_each(|h| h.on_node_error(node, &e));
+        e
+    }
+
+    /// Logs how the run ended and calls the pipeline hooks for it.
+    fn finish(&self, pipeline: &Pipeline, result: QupidoResult<Container>, start: Instant) -> QupidoResult<Container> {
+        let duration_ms = start.elapsed().as_millis() as u64;
+        Span::current().record("duration_ms", duration_ms);
+        match &result {
+            Ok(state) => {
+                info!(duration_ms, "pipeline finished");
+                self.hooks.iter().for_each(|h| h.after_pipeline_run(pipeline, state));
+            },
+            Err(e) => {
+                error!(duration_ms, error = %e, "pipeline failed");
+                self.hooks.iter().for_each(|h| h.on_pipeline_error(pipeline, e));
+            },
+        }
+        result
+    }
+
+    /// Adds the pipeline's parameters to the run state, failing on the first
+    /// one that is neither in the container nor in the attached parameters.
+    fn insert_parameters(&self, pipeline: &Pipeline, state: &mut Container) -> QupidoResult {
+        let params = pipeline.parameters();
+        if let Some(parameters) = &self.parameters {
+            parameters.insert_into(&params, state)?;
+        }
+
+        match params.iter().find(|p| !state.contains(&p.get_id())) {
+            Some(Source::Param(key)) => Err(QupidoError::ParameterNotFound(key.clone())),
+            _ => Ok(())
+        }
+    }
+
+    fn save_outputs_blocking(&self, node: &Node, state: &Container) -> QupidoResult {
+        match &self.catalog {
+            Some(catalog) => {
+                let saved = futures::executor::block_on(catalog.save_persistent(&node.outputs.outputs(), state))?;
+                self.saved(node, &saved);
+                Ok(())
+            },
+            None => Ok(())
+        }
+    }
+
+    fn release(&self, pipeline: &Pipeline) -> Option<Release> {
+        self.keep.as_ref().map(|keep| Release::new(pipeline, keep))
+    }
+
+    fn loaded(&self, sources: &[Source]) {
+        for s in sources {
+            self.hooks.iter().for_each(|h| h.after_dataset_loaded(s));
+        }
+    }
+
+    fn saved(&self, node: &Node, sources: &[Source]) {
+        for s in sources {
+            self.hooks.iter().for_each(|h| h.after_dataset_saved(node, s));
+        }
+    }
+}
+
+/// Checks the outputs and declared types of every node before the run
+/// starts, so a mismatch doesn't surface halfway through.
+fn check(pipeline: &Pipeline) -> QupidoResult {
+    pipeline.nodes.iter().try_for_each(|n| n.check_outputs())?;
+    pipeline.validate()
+}
+
+/// Calls a sync node function on a thread of its own, for the async runner:
+/// the node neither blocks the runtime nor waits for the other nodes in
+/// flight. A panic in the node is resumed where the result is awaited.
+fn call_on_thread(f: Arc<Box<SyncNodeFn>>, ctx: Context, span: Span) -> NodeFuture {
+    let (tx, rx) = oneshot::channel();
+    // the thread logs to the subscriber of the calling one, even if it is not the global one
+    let dispatch = dispatcher::get_default(|d| d.clone());
+    thread::spawn(move || {
+        let res = dispatcher::with_default(&dispatch, || span.in_scope(|| panic::catch_unwind(AssertUnwindSafe(|| f(&ctx)))));
+        // the receiver is only gone when the run was dropped
+        let _ = tx.send(res);
+    });
+
+    async move {
+        match rx.await.expect("node threads always send a result") {
+            Ok(res) => res,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }.boxed()
+}
+
+fn pipeline_span(pipeline: &Pipeline, workers: usize, run_id: Uuid) -> Span {
+    info_span!("pipeline_run", nodes = pipeline.nodes.len(), workers, run_id = %run_id, duration_ms = field::Empty)
+}
+
+fn node_span(node: &Node) -> Span {
+    let ids = |sources: Vec<Source>| sources.iter().map(|s| s.get_id()).collect::<Vec<_>>().join(",");
+    let tags = node.tags.iter().map(|t| match t {
+        Tag::Tag(t) => t.as_str(),
+    }).collect::<Vec<_>>().join(",");
+
+    let span = info_span!(
+        "node",
+        name = %node.name,
+        namespace = field::Empty,
+        tags = %tags,
+        inputs = %ids(node.inputs.inputs()),
+        outputs = %ids(node.outputs.outputs()),
+        duration_ms = field::Empty
+    );
+    if let Some(ns) = &node.namespace {
+        span.record("namespace", field::display(ns));
+    }
+    span
+}
+
+/// Counts the nodes still to consume every node output, so outputs can be
+/// dropped from the run state after their last consumer.
+struct Release {
+    pending: HashMap<Source, usize>
+}
+
+impl Release {
+    fn new(pipeline: &Pipeline, keep: &HashSet<Source>) -> Self {
+        let produced: HashSet<_> = pipeline.nodes.iter().flat_map(|n| n.outputs.outputs()).collect();
+        let mut pending = HashMap::new();
+        for source in pipeline.nodes.iter().flat_map(|n| n.inputs.inputs()) {
+            if produced.contains(&source) && !keep.contains(&source) {
+                *pending.entry(source).or_insert(0) += 1;
+            }
+        }
+        Release {
+            pending
+        }
+    }
+
+    fn consumed(&mut self, node: &Node, state: &mut Container) {
+        for source in node.inputs.inputs() {
+            if let Some(count) = self.pending.get_mut(&source) {
+                *count -= 1;
+                if *count == 0 {
+                    self.pending.remove(&source);
+                    state.data.remove(&source.get_id());
+                }
+            }
+        }
+    }
+}
+
+/// Tracks which nodes of a pipeline are ready to run, based on how many of
+/// their incoming edges have not been satisfied yet.
+struct Schedule<'p> {
+    pipeline: &'p Pipeline,
+    nodes: HashMap<Uuid, &'p Node>,
+    // position in the topological order, used to hand out ready nodes deterministically
+    rank: HashMap<Uuid, usize>,
+    pending: HashMap<NodeIndex, usize>,
+    ready: VecDeque<NodeIndex>
+}
+
+impl<'p> Schedule<'p> {
+    fn new(pipeline: &'p Pipeline) -> Self {
+        let graph = &pipeline.graph;
+        let nodes = pipeline.nodes.iter().map(|n| (n.id, n)).collect();
+        let rank = pipeline.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let pending: HashMap<NodeIndex, usize> = graph.node_indices()
+            .map(|idx| (idx, graph.edges_directed(idx, Direction::Incoming).count()))
+            .collect();
+
+        let mut s = Schedule {
+            pipeline,
+            nodes,
+            rank,
+            pending,
+            ready: VecDeque::new()
+        };
+        let initial: Vec<_> = s.pending.iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(idx, _)| *idx)
+            .collect();
+        s.push_ready(initial);
+        s
+    }
+
+    fn node(&self, idx: NodeIndex) -> &'p Node {
+        self.nodes[&self.pipeline.graph[idx]]
+    }
+
+    fn pop_ready(&mut self) -> Option<NodeIndex> {
+        self.ready.pop_front()
+    }
+
+    /// Marks a node as done and queues every successor whose inputs are now complete.
+    fn complete(&mut self, idx: NodeIndex) {
+        let mut unlocked = vec![];
+        for e in self.pipeline.graph.edges_directed(idx, Direction::Outgoing) {
+            if let Some(count) = self.pending.get_mut(&e.target()) {
+                *count -= 1;
+                if *count == 0 {
+                    unlocked.push(e.target());
+                }
+            }
+        }
+        self.push_ready(unlocked);
+    }
+
+    /// Stops handing out new nodes, used once a node has failed.
+    fn abort(&mut self) {
+        self.ready.clear();
+    }
+
+    fn push_ready(&mut self, mut indices: Vec<NodeIndex>) {
+        indices.sort_by_key(|idx| self.rank[&self.pipeline.graph[*idx]]);
+        self.ready.extend(indices);
+    }
+}
+
+
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_parallel_matches_sequential() -> QupidoResult {
+    use crate::id;
+
+    let mut nodes = vec![];
+    for i in 0..8 {
+        nodes.push(Node::new([id("seed")], [id(format!("branch_{}", i))], move |ctx| {
+            let seed: &u64 = ctx.inputs.get("seed")?;
+            let mut r = Container::new();
+            r.insert(&format!("branch_{}", i), seed * (i + 1))?;
+            Ok(r)
+        }));
+    }
+    let branches: Vec<_> = (0..8).map(|i| id(format!("branch_{}", i))).collect();
+    nodes.push(Node::new(branches.as_slice(), [id("total")], |ctx| {
+        let mut total = 0_u64;
+        for i in 0..8 {
+            total += ctx.inputs.get::<u64>(&format!("branch_{}", i))?;
+        }
+        let mut r = Container::new();
+        r.insert("total", total)?;
+        Ok(r)
+    }));
+
+    let pipeline = Pipeline::from_nodes(&nodes)?;
+    let mut container = Container::new();
+    container.insert("seed", 3_u64)?;
+
+    let sequential = pipeline.run(&container)?;
+    let parallel = pipeline.run_parallel(&container, 4)?;
+
+    let mut keys: Vec<_> = sequential.data.keys().collect();
+    keys.sort();
+    let mut parallel_keys: Vec<_> = parallel.data.keys().collect();
+    parallel_keys.sort();
+    assert_eq!(keys, parallel_keys);
+    for k in keys {
+        assert_eq!(sequential.get::<u64>(k)?, parallel.get::<u64>(k)?);
+    }
+    assert_eq!(*parallel.get::<u64>("total")?, 3 * (1 + 2 + 3 + 4 + 5 + 6 + 7 + 8));
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_runs_independent_nodes_concurrently() -> QupidoResult {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::id;
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+
+    let nodes: Vec<_> = (0..4).map(|i| {
+        let running = running.clone();
+        let max_running = max_running.clone();
+        Node::new((), [id(format!("out_{}", i))], move |_ctx| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            running.fetch_sub(1, Ordering::SeqCst);
+
+            let mut r = Container::new();
+            r.insert(&format!("out_{}", i), i)?;
+            Ok(r)
+        })
+    }).collect();
+
+    let pipeline = Pipeline::from_nodes(&nodes)?;
+    pipeline.run_parallel(&Container::new(), 4)?;
+    assert!(max_running.load(Ordering::SeqCst) > 1);
+
+    // the async runner doesn't call sync nodes in place either
+    max_running.store(0, Ordering::SeqCst);
+    futures::executor::block_on(Runner::parallel(4).run_async(&pipeline, &Container::new()))?;
+    assert!(max_running.load(Ordering::SeqCst) > 1);
+
+    max_running.store(0, Ordering::SeqCst);
+    pipeline.run(&Container::new())?;
+    assert_eq!(max_running.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_parallel_propagates_node_errors() -> QupidoResult {
+    use crate::id;
+
+    let failing = Node::new([id("a")], [id("b")], |_ctx| {
+        Err(QupidoError::DataNotFound("nope".to_string()))
+    });
+    let downstream = Node::new([id("b")], [id("c")], |ctx| {
+        let b: &u32 = ctx.inputs.get("b")?;
+        let mut r = Container::new();
+        r.insert("c", *b)?;
+        Ok(r)
+    });
+
+    let pipeline = Pipeline::from_nodes(&[failing, downstream])?;
+    let mut container = Container::new();
+    container.insert("a", 1_u32)?;
+
+    for result in [pipeline.run(&container), pipeline.run_parallel(&container, 2)] {
+        match result {
+            Err(QupidoError::NodeFailed { node, namespace, inputs, source }) => {
+                assert_eq!(node, "[a] -> [b]");
+                assert_eq!(namespace, None);
+                assert_eq!(inputs, vec!["a"]);
+                assert!(matches!(source.downcast_ref::<QupidoError>(), Some(QupidoError::DataNotFound(id)) if id == "nope"));
+            },
+            other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_run_async_mixes_sync_and_async_nodes() -> QupidoResult {
+    use crate::id;
+
+    let double = Node::new_async([id("x")], [id("doubled")], |ctx| async move {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let mut r = Container::new();
+        r.insert("doubled", x * 2)?;
+        Ok(r)
+    });
+    let plus_one = Node::new([id("doubled")], [id("result")], |ctx| {
+        let v: &i64 = ctx.inputs.get("doubled")?;
+        let mut r = Container::new();
+        r.insert("result", v + 1)?;
+        Ok(r)
+    });
+
+    let pipeline = Pipeline::from_nodes(&[plus_one, double])?;
+    let mut container = Container::new();
+    container.insert("x", 20_i64)?;
+
+    let result = futures::executor::block_on(pipeline.run_async(&container))?;
+    assert_eq!(*result.get::<i64>("result")?, 41);
+
+    let result = futures::executor::block_on(Runner::parallel(4).run_async(&pipeline, &container))?;
+    assert_eq!(*result.get::<i64>("result")?, 41);
+
+    match pipeline.run(&container) {
+        Err(QupidoError::AsyncNodeInSyncRun(_)) => (),
+        other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_runs_are_traced() -> QupidoResult {
+    use std::io;
+    use crate::id;
+
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let double = Node::new([id("x")], [id("doubled")], |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let mut r = Container::new();
+        r.insert("doubled", x * 2)?;
+        Ok(r)
+    }).name("double").tag("math");
+    let pipeline = Pipeline::from_nodes(&[double])?.with_namespace("calc")?;
+    let mut container = Container::new();
+    container.insert("calc.x", 4_i64)?;
+
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .without_time()
+        .finish();
+    let (succeeded, failed) = tracing::subscriber::with_default(subscriber, || -> QupidoResult<_> {
+        let (result, succeeded) = Runner::parallel(2).run_with_report(&pipeline, &container);
+        result?;
+        let (result, failed) = Runner::sequential().run_with_report(&pipeline, &Container::new());
+        assert!(result.is_err());
+        Ok((succeeded.run_id, failed.run_id))
+    })?;
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).map_err(QupidoError::external)?;
+    // the span carries the run id of the report
+    assert!(logs.contains(&format!(" INFO pipeline_run{{nodes=1 workers=2 run_id={}}}:node{{name=calc.double tags=math inputs=calc.x outputs=calc.doubled namespace=calc}}: qupido::runner: node started\n", succeeded)));
+    assert!(logs.contains("qupido::runner: node finished duration_ms="));
+    assert!(logs.contains("qupido::runner: pipeline finished duration_ms="));
+    assert!(logs.contains(&format!("ERROR pipeline_run{{nodes=1 workers=1 run_id={}}}:node{{name=calc.double tags=math inputs=calc.x outputs=calc.doubled namespace=calc}}: qupido::runner: node failed error=node 'calc.double'", failed)));
+
+    Ok(())
+}
+
+#[test]
+fn test_free_intermediates() -> QupidoResult {
+    use crate::{id, node::pass};
+
+    /// Nodes only see what they declared.
+    #[derive(Debug)]
+    struct DeclaredOnly;
+
+    impl Hook for DeclaredOnly {
+        fn before_node_run(&self, node: &Node, inputs: &Container) -> QupidoResult {
+            assert_eq!(inputs.data.len(), node.inputs.inputs().len());
+            Ok(())
+        }
+    }
+
+    let pipeline = Pipeline::from_nodes(&[
+        pass("x", &["in"], &["x"]),
+        pass("y", &["x"], &["y"]),
+        pass("z", &["y"], &["z"]),
+        pass("w", &["x"], &["w"]),
+    ])?;
+    let mut container = Container::new();
+    container.insert("in", 0_i64)?;
+    container.insert("unused", 0_i64)?;
+
+    for runner in [Runner::sequential(), Runner::parallel(2)] {
+        let result = runner.with_hook(DeclaredOnly).free_intermediates(&[id("y")]).run(&pipeline, &container)?;
+        let mut keys: Vec<_> = result.data.keys().map(|k| k.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["in", "unused", "w", "y", "z"]);
+    }
+    let result = futures::executor::block_on(Runner::sequential().free_intermediates(&[]).run_async(&pipeline, &container))?;
+    assert!(!result.contains("x") && !result.contains("y"));
+    assert_eq!(*result.get::<i64>("z")?, 3);
+    assert_eq!(pipeline.run(&container)?.data.len(), 6);
+
+    Ok(())
+}
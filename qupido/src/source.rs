@@ -1,6 +1,10 @@
+use std::any::Any;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::marker::PhantomData;
 
-use crate::Source;
+use crate::{container::DataType, id, Source};
 
 
 #[derive(Debug, Clone)]
@@ -24,6 +28,19 @@ impl NodeSources {
         }
     }
 
+    /// Pairs of the id the node function uses and the id in the pipeline.
+    /// For lists both are the same; maps are sorted by the node's ids.
+    pub fn pairs(&self) -> Vec<(Source, Source)> {
+        match self {
+            NodeSources::List(l) => l.iter().map(|s| (s.clone(), s.clone())).collect(),
+            NodeSources::Map(m) => {
+                let mut r: Vec<_> = m.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                r.sort();
+                r
+            },
+        }
+    }
+
     pub fn map<F>(&self, map: F) -> Self
         where F: Fn(&Source) -> Source
     {
@@ -93,3 +110,126 @@ impl From<()> for NodeSources {
 
 
 
+
+/// A source id together with the type of the value behind it. Nodes built
+/// with [`node!`](crate::node!) read their inputs and write their outputs
+/// through keys, so a function whose signature doesn't match the keys it is
+/// wired to fails to compile.
+///
+/// Keys can be constants, ids starting with `params:` refer to parameters:
+///
+/// ```ignore
+/// const RAW: SourceKey<DataFrame> = SourceKey::new("raw");
+/// const THRESHOLD: SourceKey<f64> = SourceKey::new("params:clean.threshold");
+/// ```
+pub struct SourceKey<U> {
+    id: Cow<'static, str>,
+    _type: PhantomData<fn() -> U>
+}
+
+impl<U> SourceKey<U> {
+    pub const fn new(id: &'static str) -> Self {
+        SourceKey {
+            id: Cow::Borrowed(id),
+            _type: PhantomData
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn source(&self) -> Source {
+        id(self.id.as_ref())
+    }
+}
+
+impl<U> Clone for SourceKey<U> {
+    fn clone(&self) -> Self {
+        SourceKey {
+            id: self.id.clone(),
+            _type: PhantomData
+        }
+    }
+}
+
+impl<U> Debug for SourceKey<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SourceKey").field(&self.id).field(&std::any::type_name::<U>()).finish()
+    }
+}
+
+impl<U> From<&str> for SourceKey<U> {
+    fn from(value: &str) -> Self {
+        SourceKey {
+            id: Cow::Owned(value.to_string()),
+            _type: PhantomData
+        }
+    }
+}
+
+impl<U> From<Source> for SourceKey<U> {
+    fn from(value: Source) -> Self {
+        SourceKey {
+            id: Cow::Owned(value.get_id()),
+            _type: PhantomData
+        }
+    }
+}
+
+/// What [`node!`](crate::node!) accepts as an input or output: keys, sources
+/// and plain ids.
+pub trait AsSource {
+    fn as_source(&self) -> Source;
+
+    /// The type of the value behind the source, if the key knows it.
+    fn data_type(&self) -> Option<DataType> {
+        None
+    }
+}
+
+/// The typed key of an input or output of [`node!`](crate::node!). Only a
+/// `SourceKey<U>` fixes the type, sources and ids are keys of any type.
+pub trait AsKey<U>: AsSource {
+    fn as_key(&self) -> SourceKey<U>;
+}
+
+impl<U> AsSource for SourceKey<U> where U: Any {
+    fn as_source(&self) -> Source {
+        self.source()
+    }
+
+    fn data_type(&self) -> Option<DataType> {
+        Some(DataType::of::<U>())
+    }
+}
+
+impl AsSource for Source {
+    fn as_source(&self) -> Source {
+        self.clone()
+    }
+}
+
+impl AsSource for &str {
+    fn as_source(&self) -> Source {
+        id(*self)
+    }
+}
+
+impl<U> AsKey<U> for SourceKey<U> where U: Any {
+    fn as_key(&self) -> SourceKey<U> {
+        self.clone()
+    }
+}
+
+impl<U> AsKey<U> for Source {
+    fn as_key(&self) -> SourceKey<U> {
+        SourceKey::from(self.clone())
+    }
+}
+
+impl<U> AsKey<U> for &str {
+    fn as_key(&self) -> SourceKey<U> {
+        SourceKey::from(*self)
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::{Graph, Direction, algo::tarjan_scc};
+use uuid::Uuid;
+
+use crate::{node::Node, Source};
+
+/// What [`crate::pipeline::Pipeline::from_nodes`] found wrong, or worth
+/// knowing, about a set of nodes.
+///
+/// Cycles and overwritten outputs make the nodes invalid. Unproduced sources
+/// are only looked for by [`Validation::against`], which knows what a run
+/// provides; without it every free input is legitimately unproduced, see
+/// [`crate::pipeline::Pipeline::inputs`] for those.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validation {
+    pub cycles: Vec<Cycle>,
+    /// Sources produced more than once, with the names of the producing nodes.
+    pub overwritten: Vec<(Source, Vec<String>)>,
+    /// Data sources consumed but neither produced by a node nor provided,
+    /// with the names of the consuming nodes. Parameters are not listed.
+    pub unproduced: Vec<(Source, Vec<String>)>
+}
+
+/// Nodes depending on each other in a circle. `sources[i]` is produced by
+/// `nodes[i]` and consumed by the next node, the last one wrapping around to
+/// the first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    pub nodes: Vec<String>,
+    pub sources: Vec<Source>
+}
+
+impl Validation {
+    pub fn of(nodes: &[Node]) -> Self {
+        Self::check(nodes, &graph(nodes))
+    }
+
+    /// Like [`Validation::of`], also checking that every data input is either
+    /// produced by a node or in `provided`, e.g. the sources of the catalog
+    /// and of the container a run starts with. A source nobody produces is
+    /// often a typo or a node lost while merging pipelines.
+    pub fn against(nodes: &[Node], provided: &[Source]) -> Self {
+        let mut validation = Self::of(nodes);
+        let produced: HashSet<_> = nodes.iter().flat_map(|n| n.outputs.outputs()).collect();
+
+        let mut consumers: BTreeMap<Source, Vec<String>> = BTreeMap::new();
+        for n in nodes {
+            for i in n.inputs.inputs() {
+                if !i.is_param() && !produced.contains(&i) && !provided.contains(&i) {
+                    consumers.entry(i).or_default().push(n.name.clone());
+                }
+            }
+        }
+        validation.unproduced = consumers.into_iter().collect();
+        validation
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.cycles.is_empty() && self.overwritten.is_empty() && self.unproduced.is_empty()
+    }
+
+    /// Expects the graph built by [`graph`] for the same nodes.
+    pub(crate) fn check(nodes: &[Node], g: &Graph<Uuid, Source>) -> Self {
+        let mut producers: BTreeMap<Source, Vec<String>> = BTreeMap::new();
+        for n in nodes {
+            for o in n.outputs.outputs() {
+                producers.entry(o).or_default().push(n.name.clone());
+            }
+        }
+
+        Validation {
+            cycles: cycles(nodes, g),
+            overwritten: producers.into_iter().filter(|(_, names)| names.len() > 1).collect(),
+            unproduced: vec![]
+        }
+    }
+}
+
+/// Builds the dependency graph of `nodes`, one graph node per node in the
+/// same order, with an edge from every producer of a source to every
+/// consumer of it.
+pub(crate) fn graph(nodes: &[Node]) -> Graph<Uuid, Source> {
+    let mut g = Graph::<Uuid, Source>::new();
+    let indices: Vec<_> = nodes.iter().map(|n| g.add_node(n.id)).collect();
+
+    let mut producers: HashMap<Source, Vec<NodeIndex>> = HashMap::new();
+    for (n, idx) in nodes.iter().zip(&indices) {
+        for o in n.outputs.outputs() {
+            producers.entry(o).or_default().push(*idx);
+        }
+    }
+    for (n, dst) in nodes.iter().zip(&indices) {
+        for i in n.inputs.inputs() {
+            for src in producers.get(&i).into_iter().flatten() {
+                g.add_edge(*src, *dst, i.clone());
+            }
+        }
+    }
+
+    g
+}
+
+/// One cycle per strongly connected component that has one.
+fn cycles(nodes: &[Node], g: &Graph<Uuid, Source>) -> Vec<Cycle> {
+    let mut r = vec![];
+    for scc in tarjan_scc(g) {
+        let start = *scc.iter().min().expect("components are not empty");
+        if scc.len() == 1 && g.find_edge(start, start).is_none() {
+            continue;
+        }
+
+        let members: HashSet<_> = scc.into_iter().collect();
+        if let Some(path) = path_back(g, start, &members) {
+            r.push(Cycle {
+                nodes: path.iter().map(|(idx, _)| nodes[idx.index()].name.clone()).collect(),
+                sources: path.into_iter().map(|(_, s)| s).collect()
+            });
+        }
+    }
+
+    r.sort_by(|a, b| a.nodes.cmp(&b.nodes));
+    r
+}
+
+/// Shortest walk from `start` back to itself within `members`, as the nodes
+/// visited paired with the source leading to the next one.
+fn path_back(g: &Graph<Uuid, Source>, start: NodeIndex, members: &HashSet<NodeIndex>) -> Option<Vec<(NodeIndex, Source)>> {
+    let mut parent: HashMap<NodeIndex, (NodeIndex, Source)> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(idx) = queue.pop_front() {
+        for e in g.edges_directed(idx, Direction::Outgoing) {
+            let next = e.target();
+            if next == start {
+                let mut path = vec![(idx, e.weight().clone())];
+                let mut cur = idx;
+                while cur != start {
+                    let (prev, source) = parent[&cur].clone();
+                    path.push((prev, source));
+                    cur = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if members.contains(&next) && !parent.contains_key(&next) {
+                parent.insert(next, (idx, e.weight().clone()));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    None
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (node, source) in self.nodes.iter().zip(&self.sources) {
+            write!(f, "'{}' -({})-> ", node, source.get_id())?;
+        }
+        match self.nodes.first() {
+            Some(first) => write!(f, "'{}'", first),
+            None => Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = vec![];
+        for c in &self.cycles {
+            lines.push(format!("cycle: {}", c));
+        }
+        for (source, names) in &self.overwritten {
+            lines.push(format!("'{}' is produced by more than one node: {}", source.get_id(), names.join(", ")));
+        }
+        for (source, names) in &self.unproduced {
+            lines.push(format!("'{}' is neither produced nor provided but consumed by: {}", source.get_id(), names.join(", ")));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+
+
+
+
+
+
+
+
+#[test]
+fn test_reports_cycles_and_overwritten_outputs() -> crate::QupidoResult {
+    use crate::{id, param, node::pass, pipeline::Pipeline, QupidoError};
+
+    let ingest = Pipeline::from_nodes(&[pass("load", &["raw"], &["clean"])])?;
+    let features = Pipeline::from_nodes(&[
+        pass("featurize", &["clean", "labels"], &["features"]),
+        pass("label", &["features"], &["labels"]),
+    ]);
+    match features {
+        Err(QupidoError::InvalidPipeline(v)) => {
+            assert_eq!(v.cycles, vec![Cycle {
+                nodes: vec!["featurize".to_string(), "label".to_string()],
+                sources: vec![id("features"), id("labels")]
+            }]);
+            assert_eq!(v.cycles[0].to_string(), "'featurize' -(features)-> 'label' -(labels)-> 'featurize'");
+            assert!(v.overwritten.is_empty());
+            assert!(v.unproduced.is_empty());
+        },
+        other => panic!("unexpected result: {:?}", other.map(|p| p.nodes().len())),
+    }
+
+    let train = Pipeline::from_nodes(&[pass("train", &["clean", "params:model"], &["model", "metrics"])])?;
+    let evaluate = Pipeline::from_nodes(&[pass("evaluate", &["model", "holdout"], &["metrics"])])?;
+    let stages = ingest.add(&train)?;
+    assert!(matches!(stages.add(&evaluate), Err(QupidoError::DuplicateData(id)) if id == "metrics"));
+    let v = Validation::of(&[stages.nodes(), evaluate.nodes()].concat());
+    assert!(v.cycles.is_empty());
+    assert_eq!(v.overwritten, vec![(id("metrics"), vec!["train".to_string(), "evaluate".to_string()])]);
+
+    let self_loop = Validation::of(&[pass("grow", &["size"], &["size"])]);
+    assert!(!self_loop.is_valid());
+    assert_eq!(self_loop.cycles[0].to_string(), "'grow' -(size)-> 'grow'");
+
+    let scale = [pass("scale", &["x", "params:factor"], &["y"])];
+    assert!(Validation::of(&scale).is_valid());
+    let unprovided = Validation::against(&scale, &[]);
+    assert!(!unprovided.is_valid());
+    assert_eq!(unprovided.unproduced, vec![(id("x"), vec!["scale".to_string()])]);
+    assert!(!unprovided.unproduced.iter().any(|(s, _)| *s == param("factor")));
+    assert!(Validation::against(&scale, &[id("x")]).is_valid());
+
+    let v = Validation::against(stages.nodes(), &[id("raw")]);
+    assert_eq!(v.unproduced, vec![]);
+    let v = Validation::against(&[stages.nodes(), evaluate.nodes()].concat(), &[id("raw")]);
+    assert_eq!(v.unproduced, vec![(id("holdout"), vec!["evaluate".to_string()])]);
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::process::ExitCode;
+
+use datafusion::prelude::*;
+use qupido::cache::NodeCache;
+use qupido::cli::{self, Project};
+use qupido::config::DatasetTypes;
+use qupido::{container::Container, id, node::Node, pipeline::Pipeline, registry::PipelineRegistry, report::OutputSizes, resume::RunHistory, QupidoResult};
+use qupido_data::{dataframes::{register_cache_types, register_output_sizes}, datasets::register_dataset_types};
+
+// The oscars example project, configured in `conf/`. From `qupido_data`:
+//
+//     cargo run --bin qupido -- list
+//     cargo run --bin qupido -- run --pipeline oscars
+//     cargo run --bin qupido -- viz --pipeline oscars --output oscars.html
+//
+// Runs are kept under `data/runs`, with checkpoints of the DataFrames the
+// catalog doesn't save so a failed run can be resumed with the command it
+// prints; `viz --run <run id>` adds the durations and row counts of one to
+// the drawing.
+//
+// Node outputs are cached under `data/cache`, so a second run restores them
+// instead of running the nodes; `run --force` runs them anyway and
+// `cache clear` drops the cache.
+//
+// `winners` is saved under `data/`, so after a full run the reporting nodes
+// can run on their own:
+//
+//     cargo run --bin qupido -- run --pipeline oscars --tags reporting
+
+fn oscars() -> QupidoResult<Pipeline> {
+    let winners = Node::new(id("oscar_awards"), id("winners"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("oscar_awards")?;
+        let mut c = Container::new();
+        c.insert("winners", df.clone().filter(col("winner").eq(lit(true)))?)?;
+        Ok(c)
+    }).name("winners").version("1");
+
+    let best_pictures = Node::new(id("winners"), id("best_pictures"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("winners")?;
+        let df_best_pictures = df.clone()
+            .filter(col("category").eq(lit("BEST PICTURE")))?
+            .select_columns(&["year_ceremony", "film"])?;
+
+        let mut c = Container::new();
+        c.insert("best_pictures", df_best_pictures)?;
+        Ok(c)
+    }).name("best_pictures").version("1").tag("reporting");
+
+    let categories = Node::new(id("oscar_awards"), id("oscar_categories"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("oscar_awards")?;
+        let df_categories = df.clone()
+            .select_columns(&["category"])?
+            .distinct()?
+            .sort(vec![col("category").sort(true, false)])?;
+
+        let mut c = Container::new();
+        c.insert("oscar_categories", df_categories)?;
+        Ok(c)
+    }).name("categories").version("1").tag("reporting");
+
+    Pipeline::from_nodes(&[winners, best_pictures, categories])
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt::init();
+    let ctx = SessionContext::new();
+    let mut types = DatasetTypes::new();
+    register_dataset_types(&mut types, &ctx);
+    let mut sizes = OutputSizes::new();
+    register_output_sizes(&mut sizes);
+    let mut cache = NodeCache::new("data/cache");
+    register_cache_types(&mut cache, &ctx);
+
+    let registry = match oscars().and_then(|p| PipelineRegistry::new().with("oscars", p)) {
+        Ok(registry) => registry,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let project = Project::new(registry)
+        .with_dataset_types(types)
+        .with_history(RunHistory::new("data/runs").with_checkpoint_codecs(&cache))
+        .with_cache(cache)
+        .with_output_sizes(sizes);
+    cli::main(project).await
+}
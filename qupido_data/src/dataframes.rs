@@ -0,0 +1,94 @@
+use std::hash::Hasher;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::ipc::{reader::StreamReader, writer::StreamWriter};
+use datafusion::datasource::MemTable;
+use datafusion::prelude::*;
+use qupido::cache::{NodeCache, StableHasher};
+use qupido::report::OutputSizes;
+use qupido::{QupidoError, QupidoResult};
+use serde_json::Value;
+
+/// Measures DataFrame node outputs by their number of rows in run reports.
+///
+/// Counting runs the DataFrame's plan on the tokio runtime DataFusion needs;
+/// outside of one, DataFrames get no size.
+pub fn register_output_sizes(sizes: &mut OutputSizes) {
+    sizes.register::<DataFrame>(|df| {
+        let df = df.clone();
+        Box::pin(async move {
+            tokio::runtime::Handle::try_current().map_err(QupidoError::external)?;
+            Ok(df.count().await? as u64)
+        })
+    });
+}
+
+/// Lets DataFrames be node inputs and outputs of cached nodes.
+///
+/// A DataFrame is stored as the Arrow IPC stream of its rows, and restored
+/// as a DataFrame over those rows in `ctx`. Its fingerprint hashes the same
+/// stream, that is the schema its plan produces and the data, but not the
+/// plan itself: a restored DataFrame scans a memory table, and the nodes
+/// after it should still find their cached outputs.
+///
+/// Both run the DataFrame's plan, so they need the tokio runtime of an
+/// async run.
+pub fn register_cache_types(cache: &mut NodeCache, ctx: &SessionContext) {
+    let c = ctx.clone();
+    cache.register_with::<DataFrame>(
+        |df| {
+            let df = df.clone();
+            Box::pin(async move {
+                let mut hasher = StableHasher::new();
+                write_ipc(df, HashWriter(&mut hasher)).await?;
+                Ok(hasher.finish().to_le_bytes().to_vec())
+            })
+        },
+        |df| {
+            let df = df.clone();
+            Box::pin(async move {
+                let bytes = write_ipc(df, vec![]).await?;
+                Ok(Value::String(STANDARD.encode(bytes)))
+            })
+        },
+        move |v| {
+            let Value::String(encoded) = v else {
+                return Err(QupidoError::DatasetError("a cached DataFrame has to be a base64 string".to_string()));
+            };
+            let reader = StreamReader::try_new(Cursor::new(STANDARD.decode(encoded).map_err(QupidoError::external)?), None)?;
+            let schema = reader.schema();
+            let batches = reader.collect::<Result<Vec<_>, _>>()?;
+            let table = MemTable::try_new(schema, vec![batches])?;
+            Ok(c.read_table(Arc::new(table))?)
+        }
+    );
+}
+
+/// Runs `df` and writes its schema and rows to `writer` as an Arrow IPC stream.
+async fn write_ipc<W: Write>(df: DataFrame, writer: W) -> QupidoResult<W> {
+    tokio::runtime::Handle::try_current().map_err(QupidoError::external)?;
+    let schema = Schema::from(df.schema());
+    let batches = df.collect().await?;
+    let mut writer = StreamWriter::try_new(writer, &schema)?;
+    for batch in &batches {
+        writer.write(batch)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Feeds the bytes written to it into a hasher.
+struct HashWriter<'a>(&'a mut StableHasher);
+
+impl Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::ipc::reader::FileReader;
+use datafusion::arrow::ipc::writer::FileWriter;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::*;
+use qupido::catalog::{Dataset, DatasetFuture};
+use qupido::config::DatasetTypes;
+use qupido::container::ContainerData;
+use qupido::QupidoError;
+
+/// Registers the DataFrame datasets under the catalog config types `csv`,
+/// `parquet`, `json` and `arrow_ipc`. All of them read into `ctx`.
+///
+/// `csv` understands the load args `has_header` and `delimiter`, the other
+/// types take no args; unknown args fail with a `ConfigError`.
+pub fn register_dataset_types(types: &mut DatasetTypes, ctx: &SessionContext) {
+    let c = ctx.clone();
+    types.register("csv", move |spec| {
+        spec.expect_args(&["has_header", "delimiter"], &[])?;
+        let mut dataset = CsvDataset::new(&c, spec.path()?);
+        if let Some(has_header) = spec.load_arg::<bool>("has_header")? {
+            dataset = dataset.has_header(has_header);
+        }
+        if let Some(delimiter) = spec.load_arg::<String>("delimiter")? {
+            let delimiter = delimiter.bytes().next()
+                .ok_or_else(|| QupidoError::ConfigError(format!("dataset '{}' has an empty delimiter", spec.source.get_id())))?;
+            dataset = dataset.delimiter(delimiter);
+        }
+        Ok(Arc::new(dataset))
+    });
+
+    let c = ctx.clone();
+    types.register("parquet", move |spec| {
+        spec.expect_args(&[], &[])?;
+        Ok(Arc::new(ParquetDataset::new(&c, spec.path()?)))
+    });
+
+    let c = ctx.clone();
+    types.register("json", move |spec| {
+        spec.expect_args(&[], &[])?;
+        Ok(Arc::new(JsonDataset::new(&c, spec.path()?)))
+    });
+
+    let c = ctx.clone();
+    types.register("arrow_ipc", move |spec| {
+        spec.expect_args(&[], &[])?;
+        Ok(Arc::new(ArrowIpcDataset::new(&c, spec.path()?)))
+    });
+}
+
+fn dataset_error(path: &str, e: impl Display) -> QupidoError {
+    QupidoError::DatasetError(format!("{}: {}", path, e))
+}
+
+/// Creates the directories `path` goes into, the writers only create the
+/// last component.
+fn create_parent(path: &str) -> Result<(), QupidoError> {
+    match Path::new(path).parent() {
+        Some(parent) => std::fs::create_dir_all(parent).map_err(|e| dataset_error(path, e)),
+        None => Ok(()),
+    }
+}
+
+/// Removes what an earlier save wrote to `path`, the DataFrame writers
+/// refuse to write into an existing directory.
+fn clear_path(path: &str) -> Result<(), QupidoError> {
+    let p = Path::new(path);
+    let removed = match p.is_dir() {
+        true => std::fs::remove_dir_all(p),
+        false if p.exists() => std::fs::remove_file(p),
+        false => Ok(()),
+    };
+    removed.map_err(|e| dataset_error(path, e))?;
+    create_parent(path)
+}
+
+fn path_exists(path: &str) -> DatasetFuture<'_, bool> {
+    let exists = Path::new(path).exists();
+    Box::pin(async move { Ok(exists) })
+}
+
+/// A CSV file, or a directory of CSV files, loaded as a [`DataFrame`].
+/// Saving writes one file per partition into `path`, replacing what an
+/// earlier save wrote there.
+#[derive(Clone)]
+pub struct CsvDataset {
+    ctx: SessionContext,
+    path: String,
+    has_header: bool,
+    delimiter: u8
+}
+
+impl CsvDataset {
+    pub fn new(ctx: &SessionContext, path: impl Into<String>) -> Self {
+        CsvDataset {
+            ctx: ctx.clone(),
+            path: path.into(),
+            has_header: true,
+            delimiter: b','
+        }
+    }
+
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+}
+
+impl fmt::Debug for CsvDataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsvDataset").field("path", &self.path).finish()
+    }
+}
+
+impl Dataset for CsvDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        Box::pin(async move {
+            let options = CsvReadOptions::new()
+                .has_header(self.has_header)
+                .delimiter(self.delimiter);
+            let df = self.ctx.read_csv(&self.path, options).await.map_err(|e| dataset_error(&self.path, e))?;
+            Ok(Arc::new(df) as Arc<dyn ContainerData>)
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        Box::pin(async move {
+            let df = data.try_downcast_ref::<DataFrame>(&self.path)?;
+            clear_path(&self.path)?;
+            df.clone().write_csv(&self.path).await.map_err(|e| dataset_error(&self.path, e))
+        })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        path_exists(&self.path)
+    }
+
+    fn needs_runtime(&self) -> bool {
+        true
+    }
+}
+
+/// A Parquet file, or a directory of Parquet files, loaded as a [`DataFrame`].
+#[derive(Clone)]
+pub struct ParquetDataset {
+    ctx: SessionContext,
+    path: String
+}
+
+impl ParquetDataset {
+    pub fn new(ctx: &SessionContext, path: impl Into<String>) -> Self {
+        ParquetDataset {
+            ctx: ctx.clone(),
+            path: path.into()
+        }
+    }
+}
+
+impl fmt::Debug for ParquetDataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParquetDataset").field("path", &self.path).finish()
+    }
+}
+
+impl Dataset for ParquetDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        Box::pin(async move {
+            let df = self.ctx.read_parquet(&self.path, ParquetReadOptions::default()).await.map_err(|e| dataset_error(&self.path, e))?;
+            Ok(Arc::new(df) as Arc<dyn ContainerData>)
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        Box::pin(async move {
+            let df = data.try_downcast_ref::<DataFrame>(&self.path)?;
+            clear_path(&self.path)?;
+            df.clone().write_parquet(&self.path, None).await.map_err(|e| dataset_error(&self.path, e))
+        })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        path_exists(&self.path)
+    }
+
+    fn needs_runtime(&self) -> bool {
+        true
+    }
+}
+
+/// Newline delimited JSON, loaded as a [`DataFrame`].
+#[derive(Clone)]
+pub struct JsonDataset {
+    ctx: SessionContext,
+    path: String
+}
+
+impl JsonDataset {
+    pub fn new(ctx: &SessionContext, path: impl Into<String>) -> Self {
+        JsonDataset {
+            ctx: ctx.clone(),
+            path: path.into()
+        }
+    }
+}
+
+impl fmt::Debug for JsonDataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonDataset").field("path", &self.path).finish()
+    }
+}
+
+impl Dataset for JsonDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        Box::pin(async move {
+            let df = self.ctx.read_json(&self.path, NdJsonReadOptions::default()).await.map_err(|e| dataset_error(&self.path, e))?;
+            Ok(Arc::new(df) as Arc<dyn ContainerData>)
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        Box::pin(async move {
+            let df = data.try_downcast_ref::<DataFrame>(&self.path)?;
+            clear_path(&self.path)?;
+            df.clone().write_json(&self.path).await.map_err(|e| dataset_error(&self.path, e))
+        })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        path_exists(&self.path)
+    }
+
+    fn needs_runtime(&self) -> bool {
+        true
+    }
+}
+
+/// A single Arrow IPC file. Loading reads all record batches into memory.
+#[derive(Clone)]
+pub struct ArrowIpcDataset {
+    ctx: SessionContext,
+    path: String
+}
+
+impl ArrowIpcDataset {
+    pub fn new(ctx: &SessionContext, path: impl Into<String>) -> Self {
+        ArrowIpcDataset {
+            ctx: ctx.clone(),
+            path: path.into()
+        }
+    }
+}
+
+impl fmt::Debug for ArrowIpcDataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArrowIpcDataset").field("path", &self.path).finish()
+    }
+}
+
+impl Dataset for ArrowIpcDataset {
+    fn load(&self) -> DatasetFuture<'_, Arc<dyn ContainerData>> {
+        Box::pin(async move {
+            let file = File::open(&self.path).map_err(|e| dataset_error(&self.path, e))?;
+            let reader = FileReader::try_new(file, None).map_err(|e| dataset_error(&self.path, e))?;
+            let schema = reader.schema();
+            let batches = reader.collect::<Result<Vec<_>, _>>().map_err(|e| dataset_error(&self.path, e))?;
+
+            let table = MemTable::try_new(schema, vec![batches]).map_err(|e| dataset_error(&self.path, e))?;
+            let df = self.ctx.read_table(Arc::new(table)).map_err(|e| dataset_error(&self.path, e))?;
+            Ok(Arc::new(df) as Arc<dyn ContainerData>)
+        })
+    }
+
+    fn save(&self, data: Arc<dyn ContainerData>) -> DatasetFuture<'_> {
+        Box::pin(async move {
+            let df = data.try_downcast_ref::<DataFrame>(&self.path)?;
+            let schema: Schema = df.schema().clone().into();
+            let batches = df.clone().collect().await.map_err(|e| dataset_error(&self.path, e))?;
+
+            create_parent(&self.path)?;
+            let file = File::create(&self.path).map_err(|e| dataset_error(&self.path, e))?;
+            let mut writer = FileWriter::try_new(file, &schema).map_err(|e| dataset_error(&self.path, e))?;
+            for batch in &batches {
+                writer.write(batch).map_err(|e| dataset_error(&self.path, e))?;
+            }
+            writer.finish().map_err(|e| dataset_error(&self.path, e))
+        })
+    }
+
+    fn exists(&self) -> DatasetFuture<'_, bool> {
+        path_exists(&self.path)
+    }
+
+    fn needs_runtime(&self) -> bool {
+        true
+    }
+}
@@ -1,3 +1,6 @@
+pub mod dataframes;
+pub mod datasets;
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
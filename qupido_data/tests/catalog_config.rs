@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use datafusion::prelude::*;
+use qupido::{QupidoResult, config::{ConfigLoader, DatasetTypes}, container::Container, node::Node, id, pipeline::Pipeline, runner::Runner};
+use qupido_data::datasets::register_dataset_types;
+
+#[tokio::test]
+async fn test_catalog_from_config() -> QupidoResult {
+    let ctx = SessionContext::new();
+    let mut types = DatasetTypes::new();
+    register_dataset_types(&mut types, &ctx);
+
+    let output_dir = std::env::temp_dir().join(format!("qupido_catalog_config_{}", std::process::id()));
+    let catalog = ConfigLoader::new("tests/conf")
+        .with_env("test")
+        .with_var("output_dir", output_dir.to_string_lossy())
+        .catalog(&types)
+        ?;
+    let catalog = Arc::new(catalog);
+
+    let node_best_pictures = Node::new(id("oscar_awards"), id("best_pictures"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("oscar_awards")?;
+        let df_best_pictures = df.clone()
+            .filter(col("winner").eq(lit(true)))?
+            .filter(col("category").eq(lit("BEST PICTURE")))?
+            .select_columns(&["year_ceremony", "film"])?;
+
+        let mut c = Container::new();
+        c.insert("best_pictures", df_best_pictures)?;
+        Ok(c)
+    });
+    let pipeline = Pipeline::from_nodes(&[node_best_pictures])?;
+
+    Runner::sequential()
+        .with_catalog(catalog.clone())
+        .run_async(&pipeline, &Container::new())
+        .await
+        ?;
+
+    assert!(output_dir.join("test").join("best_pictures").exists());
+    let best_pictures = catalog.load(&id("best_pictures")).await?;
+    best_pictures.try_downcast_ref::<DataFrame>("best_pictures")?.clone().show_limit(5).await?;
+
+    std::fs::remove_dir_all(&output_dir)?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use datafusion::prelude::*;
+use qupido::{QupidoResult, QupidoError, catalog::{DataCatalog, MemoryDataset}, container::Container, node::Node, id, pipeline::Pipeline, runner::Runner};
+use qupido_data::datasets::{CsvDataset, ParquetDataset, ArrowIpcDataset};
+
+#[tokio::test]
+async fn test_catalog_csv_to_parquet() -> QupidoResult {
+    let ctx = SessionContext::new();
+    let out_dir = std::env::temp_dir().join(format!("qupido_catalog_{}", std::process::id()));
+    std::fs::create_dir_all(&out_dir)?;
+    let best_pictures_path = out_dir.join("best_pictures").to_string_lossy().to_string();
+    let winners_path = out_dir.join("winners.arrow").to_string_lossy().to_string();
+
+    let catalog = DataCatalog::new()
+        .with(id("oscar_awards"), CsvDataset::new(&ctx, "tests/data/the_oscar_award.csv"))?
+        .with(id("best_pictures"), ParquetDataset::new(&ctx, &best_pictures_path))?
+        .with(id("winners"), ArrowIpcDataset::new(&ctx, &winners_path))?;
+    let catalog = Arc::new(catalog);
+
+    let node_winners = Node::new(id("oscar_awards"), id("winners"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("oscar_awards")?;
+        let df_winners = df.clone()
+            .filter(col("winner").eq(lit(true)))?;
+
+        let mut c = Container::new();
+        c.insert("winners", df_winners)?;
+        Ok(c)
+    });
+
+    let node_best_pictures = Node::new(id("winners"), id("best_pictures"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("winners")?;
+        let df_best_pictures = df.clone()
+            .filter(col("category").eq(lit("BEST PICTURE")))?
+            .select_columns(&["year_ceremony", "film"])?;
+
+        let mut c = Container::new();
+        c.insert("best_pictures", df_best_pictures)?;
+        Ok(c)
+    });
+
+    let pipeline = Pipeline::from_nodes(&[node_winners, node_best_pictures])?;
+    assert_eq!(pipeline.inputs(), vec![id("oscar_awards")]);
+
+    let runner = Runner::sequential().with_catalog(catalog.clone());
+    runner.run_async(&pipeline, &Container::new()).await?;
+
+    assert!(catalog.exists(&id("best_pictures")).await?);
+    assert!(catalog.exists(&id("winners")).await?);
+
+    let best_pictures = catalog.load(&id("best_pictures")).await?;
+    let best_pictures = best_pictures.try_downcast_ref::<DataFrame>("best_pictures")?;
+    let rows: usize = best_pictures.clone().collect().await?.iter().map(|b| b.num_rows()).sum();
+    assert!(rows > 0);
+
+    let winners = catalog.load(&id("winners")).await?;
+    winners.try_downcast_ref::<DataFrame>("winners")?.clone().show_limit(5).await?;
+
+    std::fs::remove_dir_all(&out_dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_save_into_missing_directories() -> QupidoResult {
+    use qupido::catalog::Dataset;
+    use qupido_data::datasets::JsonDataset;
+
+    let ctx = SessionContext::new();
+    let out_dir = std::env::temp_dir().join(format!("qupido_nested_{}", std::process::id()));
+    let nested = |name: &str| out_dir.join("a").join("b").join(name).to_string_lossy().to_string();
+    let df = ctx.read_csv("tests/data/the_oscar_award.csv", CsvReadOptions::new()).await?.limit(0, Some(10))?;
+
+    let datasets: Vec<Box<dyn Dataset>> = vec![
+        Box::new(CsvDataset::new(&ctx, nested("awards_csv"))),
+        Box::new(ParquetDataset::new(&ctx, nested("awards_parquet"))),
+        Box::new(JsonDataset::new(&ctx, nested("awards_json"))),
+        Box::new(ArrowIpcDataset::new(&ctx, nested("awards.arrow")))
+    ];
+    for dataset in &datasets {
+        assert!(!dataset.exists().await?);
+        dataset.save(Arc::new(df.clone())).await?;
+        assert!(dataset.exists().await?);
+        // saving again replaces the earlier output
+        dataset.save(Arc::new(df.clone())).await?;
+        let reloaded = dataset.load().await?;
+        assert_eq!(reloaded.try_downcast_ref::<DataFrame>("reloaded")?.clone().count().await?, 10);
+    }
+
+    std::fs::remove_dir_all(&out_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_catalog_in_sync_runner() -> QupidoResult {
+    // the DataFrame datasets need a tokio runtime, the sync runners refuse them
+    let ctx = SessionContext::new();
+    let catalog = DataCatalog::new()
+        .with(id("oscar_awards"), CsvDataset::new(&ctx, "tests/data/the_oscar_award.csv"))?
+        .with(id("winners"), MemoryDataset::new())?;
+
+    let node_winners = Node::new(id("oscar_awards"), id("winners"), |ctx| {
+        let df = ctx.inputs.get::<DataFrame>("oscar_awards")?;
+        let mut c = Container::new();
+        c.insert("winners", df.clone().filter(col("winner").eq(lit(true)))?)?;
+        Ok(c)
+    });
+    let pipeline = Pipeline::from_nodes(&[node_winners])?;
+
+    for runner in [Runner::sequential(), Runner::parallel(2)] {
+        match runner.with_catalog(Arc::new(catalog.clone())).run(&pipeline, &Container::new()) {
+            Err(QupidoError::AsyncDatasetInSyncRun(id)) => assert_eq!(id, "oscar_awards"),
+            other => panic!("unexpected result: {:?}", other.map(|c| c.data.len())),
+        }
+    }
+
+    // sources the pipeline doesn't use don't matter
+    let mut container = Container::new();
+    container.insert("x", 1_i64)?;
+    let unrelated = Pipeline::from_nodes(&[Node::new(id("x"), id("y"), |ctx| {
+        let x: &i64 = ctx.inputs.get("x")?;
+        let mut c = Container::new();
+        c.insert("y", x + 1)?;
+        Ok(c)
+    })])?;
+    let result = Runner::sequential().with_catalog(Arc::new(catalog)).run(&unrelated, &container)?;
+    assert_eq!(*result.get::<i64>("y")?, 2);
+
+    Ok(())
+}
@@ -1,51 +1,163 @@
-use datafusion::{prelude::*, error::DataFusionError};
-use qupido::{container::Container, node::Node, id, pipeline::Pipeline};
+use datafusion::{error::Result, prelude::*};
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc};
+
+use qupido::{QupidoError, QupidoResult, cache::NodeCache, container::Container, id, node, node::Node, pipeline::Pipeline, report::OutputSizes, resume::RunHistory, runner::Runner, source::SourceKey};
+use qupido_data::dataframes::{register_cache_types, register_output_sizes};
+
+const OSCAR_AWARDS: SourceKey<DataFrame> = SourceKey::new("oscar_awards");
+const OSCAR_CATEGORIES: SourceKey<DataFrame> = SourceKey::new("oscar_categories");
+const OSCAR_CATEGORIES_CLEAN: SourceKey<DataFrame> = SourceKey::new("oscar_categories_clean");
+const OSCAR_CATEGORIES_MATERIALIZED: SourceKey<DataFrame> = SourceKey::new("oscar_categories_materialized");
+
+fn categories(awards: &DataFrame) -> Result<DataFrame> {
+    awards.clone()
+        .select_columns(&["category"])?
+        .distinct()?
+        .sort(vec![col("category").sort(true, false)])
+}
+
+fn clean_categories(categories: &DataFrame) -> Result<DataFrame> {
+    categories.clone()
+        .select(vec![regexp_replace(vec![col("category"), lit("\\(.*\\)"), lit("")]).alias("category")])?
+        .select(vec![upper(trim(col("category"))).alias("clean_category")])?
+        .distinct()?
+        .sort(vec![col("clean_category").sort(true, false)])
+}
+
+async fn materialize(df: &DataFrame) -> Result<DataFrame> {
+    let batches = df.clone().collect().await?;
+    assert!(batches.iter().map(|b| b.num_rows()).sum::<usize>() > 0);
+
+    df.clone().cache().await
+}
 
 #[tokio::test]
-async fn test_oscars_pipeline() -> Result<(), DataFusionError> {
+async fn test_oscars_pipeline() -> QupidoResult {
     let ctx = SessionContext::new();
     let df = ctx.read_csv("tests/data/the_oscar_award.csv", CsvReadOptions::new()).await?;
     
     let container = {
         let mut container = Container::new();
-        container.insert("oscar_awards", df).unwrap();
+        container.insert_key(&OSCAR_AWARDS, df)?;
         container
     };
 
-    let node_categories = Node::<DataFrame>::new(id("oscar_awards"), id("oscar_categories"), |ctx| {
-        let df = ctx.inputs.get("oscar_awards")?;
-        let df_categories = df.clone()
-                              .select_columns(&["category"]).unwrap()
-                              .distinct().unwrap()
-                              .sort(vec![col("category").sort(true, false)]).unwrap();
-        
-        let mut c = Container::new();
-        c.insert("oscar_categories", df_categories).unwrap();
-        Ok(c)
-    });
-
-    let node_categories_clean = Node::<DataFrame>::new(id("oscar_categories"), id("oscar_categories_clean"), |ctx| {
-        let df = ctx.inputs.get("oscar_categories")?;
-        let df_clean = df.clone()
-            .select(vec![regexp_replace(vec![col("category"), lit("\\(.*\\)"), lit("")]).alias("category")]).unwrap()
-            .select(vec![upper(trim(col("category"))).alias("clean_category")]).unwrap()
-            .distinct().unwrap()
-            .sort(vec![col("clean_category").sort(true, false)]).unwrap();
-
-        let mut c = Container::new();
-        c.insert("oscar_categories_clean", df_clean).unwrap();
-        Ok(c)
-    });
-
-    let pipeline = Pipeline::from_nodes(&[node_categories, node_categories_clean]).unwrap();
-    let resulting_container = pipeline.run(&container).unwrap();
-
-    let categories = resulting_container.get("oscar_categories").unwrap();
+    let pipeline = Pipeline::from_nodes(&[
+        node!(categories(OSCAR_AWARDS)? -> OSCAR_CATEGORIES),
+        node!(clean_categories(OSCAR_CATEGORIES)? -> OSCAR_CATEGORIES_CLEAN),
+        node!(async materialize(OSCAR_CATEGORIES_CLEAN)? -> OSCAR_CATEGORIES_MATERIALIZED)
+    ])?;
+    let resulting_container = pipeline.run_async(&container).await?;
+
+    let categories = resulting_container.get_key(&OSCAR_CATEGORIES)?;
     categories.clone().show().await?;
 
-    let categories_clean = resulting_container.get("oscar_categories_clean").unwrap();
-    categories_clean.clone().show().await?;
+    let categories_materialized = resulting_container.get_key(&OSCAR_CATEGORIES_MATERIALIZED)?;
+    categories_materialized.clone().show().await?;
     
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_oscars_output_sizes() -> QupidoResult {
+    let ctx = SessionContext::new();
+    let mut container = Container::new();
+    container.insert_key(&OSCAR_AWARDS, ctx.read_csv("tests/data/the_oscar_award.csv", CsvReadOptions::new()).await?)?;
+
+    let pipeline = Pipeline::from_nodes(&[
+        node!(categories(OSCAR_AWARDS)? -> OSCAR_CATEGORIES),
+        node!(clean_categories(OSCAR_CATEGORIES)? -> OSCAR_CATEGORIES_CLEAN)
+    ])?;
+    let mut sizes = OutputSizes::new();
+    register_output_sizes(&mut sizes);
+    let (result, report) = Runner::sequential()
+        .with_output_sizes(Arc::new(sizes))
+        .run_async_with_report(&pipeline, &container).await;
+
+    let categories = result?.get_key(&OSCAR_CATEGORIES)?.clone().count().await?;
+    let sizes = &report.node("categories").unwrap().output_sizes;
+    assert_eq!(sizes.get("oscar_categories"), Some(&(categories as u64)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oscars_cache() -> QupidoResult {
+    let ctx = SessionContext::new();
+    let mut container = Container::new();
+    container.insert_key(&OSCAR_AWARDS, ctx.read_csv("tests/data/the_oscar_award.csv", CsvReadOptions::new()).await?)?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let pipeline = Pipeline::from_nodes(&[
+        Node::new(id("oscar_awards"), id("oscar_categories"), move |ctx| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let mut c = Container::new();
+            c.insert_key(&OSCAR_CATEGORIES, categories(ctx.inputs.get_key(&OSCAR_AWARDS)?)?)?;
+            Ok(c)
+        }).name("categories").version("1"),
+        node!(clean_categories(OSCAR_CATEGORIES)? -> OSCAR_CATEGORIES_CLEAN).version("1")
+    ])?;
+
+    let dir = std::env::temp_dir().join(format!("qupido_data_cache_{}", std::process::id()));
+    let mut cache = NodeCache::new(&dir);
+    register_cache_types(&mut cache, &ctx);
+    let runner = Runner::sequential().with_cache(Arc::new(cache.clone()));
+    let first = runner.run_async(&pipeline, &container).await?;
+    let second = runner.run_async(&pipeline, &container).await?;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // the restored frames hold the same rows, and the node after them is restored too
+    let rows = |c: &Container| c.get_key(&OSCAR_CATEGORIES_CLEAN).map(|df| df.clone().collect());
+    assert_eq!(format!("{:?}", rows(&first)?.await?), format!("{:?}", rows(&second)?.await?));
+    assert_eq!(cache.evict("clean_categories")?, 1);
+
+    cache.clear()?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oscars_resume() -> QupidoResult {
+    let ctx = SessionContext::new();
+    let mut container = Container::new();
+    container.insert_key(&OSCAR_AWARDS, ctx.read_csv("tests/data/the_oscar_award.csv", CsvReadOptions::new()).await?)?;
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let broken = Arc::new(AtomicBool::new(true));
+    let failing = broken.clone();
+    let pipeline = Pipeline::from_nodes(&[
+        Node::new(id("oscar_awards"), id("oscar_categories"), move |ctx| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let mut c = Container::new();
+            c.insert_key(&OSCAR_CATEGORIES, categories(ctx.inputs.get_key(&OSCAR_AWARDS)?)?)?;
+            Ok(c)
+        }).name("categories"),
+        Node::new(id("oscar_categories"), id("oscar_categories_clean"), move |ctx| {
+            if failing.load(Ordering::SeqCst) {
+                return Err(QupidoError::external("not yet"));
+            }
+            let mut c = Container::new();
+            c.insert_key(&OSCAR_CATEGORIES_CLEAN, clean_categories(ctx.inputs.get_key(&OSCAR_CATEGORIES)?)?)?;
+            Ok(c)
+        }).name("clean_categories")
+    ])?;
+
+    let dir = std::env::temp_dir().join(format!("qupido_data_runs_{}", std::process::id()));
+    let mut codecs = NodeCache::new(&dir);
+    register_cache_types(&mut codecs, &ctx);
+    let runner = Runner::sequential().with_history(Arc::new(RunHistory::new(&dir).with_checkpoint_codecs(&codecs)));
+    let (result, failed) = runner.run_async_with_report(&pipeline, &container).await;
+    assert!(result.is_err());
+
+    // the categories come back from their checkpoint instead of running again
+    broken.store(false, Ordering::SeqCst);
+    let (result, resumed) = runner.resume_async(&pipeline, failed.run_id, &container).await?;
+    assert!(resumed.succeeded());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(result?.get_key(&OSCAR_CATEGORIES_CLEAN)?.clone().count().await? > 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}